@@ -1,13 +1,47 @@
-use anyhow::Result;
+use crate::{config::GlobalConfig, render::human_size, structs::display_path};
+use anyhow::{Context, Result};
+use colored::Colorize;
 use libc::getuid;
+use std::cell::OnceCell;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use time::{Date, Month, OffsetDateTime, PrimitiveDateTime, Time, UtcOffset};
 
-pub fn backup(backupdir: &Path, path: &Path) -> Result<()> {
+/// Where backups were kept before they moved to the state directory.
+const LEGACY_BACKUPS: &str = ".backups";
+
+/// The backup session of a `link` or `copy` run, resolved on the first
+/// backup so that runs replacing nothing do not read the global config.
+pub struct BackupDir(OnceCell<PathBuf>);
+
+impl BackupDir {
+    pub fn session() -> BackupDir {
+        BackupDir(OnceCell::new())
+    }
+
+    #[cfg(test)]
+    pub fn at(path: &Path) -> BackupDir {
+        BackupDir(OnceCell::from(path.to_path_buf()))
+    }
+
+    fn path(&self) -> Result<&Path> {
+        if let Some(path) = self.0.get() {
+            return Ok(path);
+        }
+        let path = get_backuppath()?;
+        Ok(self.0.get_or_init(|| path))
+    }
+}
+
+pub fn backup(backupdir: &BackupDir, path: &Path) -> Result<()> {
+    let backupdir = backupdir.path()?;
     let mut components = path.components();
     components.next();
     let backup = backupdir.join(components.as_path());
-    fs::create_dir_all(backup.parent().unwrap_or(backupdir)).expect("create backup dir");
+    let parent = backup.parent().unwrap_or(backupdir);
+    fs::create_dir_all(parent)
+        .with_context(|| format!("cannot create backup dir {}", display_path(parent)))?;
     Ok(fs::rename(path, backup)?)
 }
 
@@ -15,7 +49,7 @@ pub fn backup(backupdir: &Path, path: &Path) -> Result<()> {
 fn test_backup() -> Result<()> {
     let backupdir = PathBuf::from("test/backup");
     let path = PathBuf::from("test/repo/bash/.bashrc");
-    backup(&backupdir, &path)?;
+    backup(&BackupDir::at(&backupdir), &path)?;
     // roll back
     let mut components = path.components();
     components.next();
@@ -26,9 +60,23 @@ fn test_backup() -> Result<()> {
     Ok(())
 }
 
+/// Backups of the current user, one session directory per run below
+/// `YYYY/MM/DD`.
+pub fn user_backups() -> Result<PathBuf> {
+    let root = GlobalConfig::new()?.backup_root()?;
+    let legacy = Path::new(LEGACY_BACKUPS);
+    if legacy.is_dir() && legacy.canonicalize().ok() != root.canonicalize().ok() {
+        tracing::warn!(
+            "{} holds backups of an earlier version, which are no longer listed or pruned; move its uid* directories into {}",
+            display_path(&std::path::absolute(legacy)?),
+            display_path(&root)
+        );
+    }
+    Ok(root.join(format!("uid{}", unsafe { getuid() })))
+}
+
 pub fn get_backuppath() -> Result<PathBuf> {
-    let mut backupdir = PathBuf::from(".backups");
-    backupdir.push(format!("uid{}", unsafe { getuid() }));
+    let mut backupdir = user_backups()?;
     let local = time::OffsetDateTime::now_local()?;
     backupdir.push(format_backuptime(local)?);
     Ok(backupdir)
@@ -50,3 +98,241 @@ fn formats_backup_time() -> Result<()> {
     assert_eq!(format_backuptime(datetime)?, "2026/07/19/12:34:56");
     Ok(())
 }
+
+/// A backup session: the files replaced by one `link` or `copy` run.
+#[derive(Debug)]
+struct Session {
+    path: PathBuf,
+    time: OffsetDateTime,
+    files: usize,
+    size: u64,
+}
+
+/// Parses the `YYYY/MM/DD/HH:MM:SS` path of a session as local time.
+fn parse_backuptime(rel: &Path) -> Option<OffsetDateTime> {
+    let parts = rel
+        .iter()
+        .map(|part| part.to_str())
+        .collect::<Option<Vec<_>>>()?;
+    let [year, month, day, hms] = parts[..] else {
+        return None;
+    };
+    let mut hms = hms.split(':').map(|n| n.parse::<u8>().ok());
+    let (Some(Some(hour)), Some(Some(minute)), Some(Some(second)), None) =
+        (hms.next(), hms.next(), hms.next(), hms.next())
+    else {
+        return None;
+    };
+    let month = Month::try_from(month.parse::<u8>().ok()?).ok()?;
+    let date = Date::from_calendar_date(year.parse().ok()?, month, day.parse().ok()?).ok()?;
+    let time = Time::from_hms(hour, minute, second).ok()?;
+    let offset = UtcOffset::current_local_offset().unwrap_or(UtcOffset::UTC);
+    Some(PrimitiveDateTime::new(date, time).assume_offset(offset))
+}
+
+/// Number of files and bytes below `path`.
+fn usage(path: &Path) -> Result<(usize, u64)> {
+    let meta = fs::symlink_metadata(path)?;
+    if !meta.is_dir() {
+        return Ok((1, meta.len()));
+    }
+    let mut total = (0, 0);
+    for entry in fs::read_dir(path)? {
+        let (files, size) = usage(&entry?.path())?;
+        total = (total.0 + files, total.1 + size);
+    }
+    Ok(total)
+}
+
+/// Sessions found at the session depth below `root`, oldest first.
+fn list_sessions(root: &Path) -> Result<Vec<Session>> {
+    let mut sessions = vec![];
+    let mut dirs = vec![root.to_path_buf()];
+    for _ in 0..4 {
+        let mut children = vec![];
+        for dir in dirs {
+            let Ok(entries) = fs::read_dir(&dir) else {
+                continue;
+            };
+            for entry in entries {
+                let entry = entry?;
+                if entry.file_type()?.is_dir() {
+                    children.push(entry.path());
+                }
+            }
+        }
+        dirs = children;
+    }
+    for path in dirs {
+        let rel = path.strip_prefix(root).unwrap_or(&path);
+        let Some(time) = parse_backuptime(rel) else {
+            continue;
+        };
+        let (files, size) = usage(&path)?;
+        sessions.push(Session {
+            path,
+            time,
+            files,
+            size,
+        });
+    }
+    sessions.sort_by_key(|s| s.time);
+    Ok(sessions)
+}
+
+fn format_session(session: &Session) -> String {
+    let rel = session.path.iter().rev().take(4).collect::<Vec<_>>();
+    let rel = rel.into_iter().rev().collect::<PathBuf>();
+    display_path(&rel)
+}
+
+pub fn list_backups() -> Result<()> {
+    let root = user_backups()?;
+    let sessions = list_sessions(&root)?;
+    if sessions.is_empty() {
+        eprintln!("no backups in {}", display_path(&root));
+        return Ok(());
+    }
+    println!(
+        "{}",
+        format!("{:19}  {:>5}  {:>7}", "SESSION", "FILES", "SIZE").bold()
+    );
+    for session in &sessions {
+        println!(
+            "{:19}  {:>5}  {:>7}",
+            format_session(session),
+            session.files,
+            human_size(session.size)
+        );
+    }
+    let files = sessions.iter().map(|s| s.files).sum::<usize>();
+    let size = sessions.iter().map(|s| s.size).sum::<u64>();
+    eprintln!(
+        "{} sessions, {files} files, {} in {}",
+        sessions.len(),
+        human_size(size),
+        display_path(&root)
+    );
+    Ok(())
+}
+
+/// Which sessions `prune` keeps: a session is kept when any given rule
+/// keeps it.
+#[derive(Debug, Default)]
+pub struct Retention {
+    /// Keep the newest sessions.
+    pub keep_last: Option<usize>,
+    /// Keep the sessions younger than this.
+    pub older_than: Option<Duration>,
+}
+
+impl Retention {
+    /// Sessions, oldest first, to remove at `now`.
+    fn expired<'a>(&self, sessions: &'a [Session], now: OffsetDateTime) -> Vec<&'a Session> {
+        let newest = sessions.len().saturating_sub(self.keep_last.unwrap_or(0));
+        sessions
+            .iter()
+            .enumerate()
+            .filter(|(i, session)| {
+                let kept_by_count = self.keep_last.is_some() && *i >= newest;
+                let kept_by_age = self.older_than.is_some_and(|age| now - session.time < age);
+                !kept_by_count && !kept_by_age
+            })
+            .map(|(_, session)| session)
+            .collect()
+    }
+}
+
+/// Removes `dir` and its parents up to `root` as long as they are empty.
+fn remove_empty_parents(root: &Path, dir: &Path) {
+    for dir in dir.ancestors().take_while(|d| *d != root) {
+        if fs::remove_dir(dir).is_err() {
+            break;
+        }
+    }
+}
+
+pub fn prune_backups(retention: &Retention, dry_run: bool) -> Result<()> {
+    let root = user_backups()?;
+    let sessions = list_sessions(&root)?;
+    let expired = retention.expired(&sessions, OffsetDateTime::from(SystemTime::now()));
+    for session in &expired {
+        let label = if dry_run { "WOULD PRUNE" } else { "PRUNED" };
+        eprintln!(
+            "{}: {} ({} files, {})",
+            label.yellow(),
+            format_session(session),
+            session.files,
+            human_size(session.size)
+        );
+        if !dry_run {
+            fs::remove_dir_all(&session.path)
+                .with_context(|| format!("cannot remove {}", display_path(&session.path)))?;
+            remove_empty_parents(&root, session.path.parent().unwrap_or(&root));
+        }
+    }
+    eprintln!("{} of {} sessions pruned", expired.len(), sessions.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(rel: &str) -> Session {
+        Session {
+            path: PathBuf::from(rel),
+            time: parse_backuptime(Path::new(rel)).unwrap(),
+            files: 1,
+            size: 1,
+        }
+    }
+
+    #[test]
+    fn parses_session_paths() {
+        assert!(parse_backuptime(Path::new("2026/07/19/12:34:56")).is_some());
+        assert!(parse_backuptime(Path::new("2026/13/19/12:34:56")).is_none());
+        assert!(parse_backuptime(Path::new("2026/07/19/12:34")).is_none());
+        assert!(parse_backuptime(Path::new("2026/07/19")).is_none());
+    }
+
+    #[test]
+    fn keeps_sessions_kept_by_any_rule() {
+        let sessions = [
+            session("2026/07/01/00:00:00"),
+            session("2026/07/10/00:00:00"),
+            session("2026/07/18/00:00:00"),
+            session("2026/07/19/00:00:00"),
+        ];
+        let now = sessions[3].time + Duration::from_secs(60);
+        let names = |retention: Retention| {
+            retention
+                .expired(&sessions, now)
+                .iter()
+                .map(|s| format_session(s))
+                .collect::<Vec<_>>()
+        };
+        let day = Duration::from_secs(24 * 60 * 60);
+        assert_eq!(
+            names(Retention {
+                keep_last: Some(3),
+                older_than: None,
+            }),
+            ["2026/07/01/00:00:00"]
+        );
+        assert_eq!(
+            names(Retention {
+                keep_last: None,
+                older_than: Some(5 * day),
+            }),
+            ["2026/07/01/00:00:00", "2026/07/10/00:00:00"]
+        );
+        assert_eq!(
+            names(Retention {
+                keep_last: Some(3),
+                older_than: Some(5 * day),
+            }),
+            ["2026/07/01/00:00:00"]
+        );
+    }
+}
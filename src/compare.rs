@@ -0,0 +1,233 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+const CHUNK_SIZE: usize = 64 * 1024;
+/// Bytes looked at for NUL bytes to tell binary from text, as git does.
+const SNIFF_SIZE: usize = 8000;
+/// Largest file loaded for a text diff when the global config sets none.
+pub const DEFAULT_MAX_DIFF_SIZE: u64 = 1024 * 1024;
+
+/// Outcome of comparing a source with its existing target.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Same,
+    Text(Vec<String>, Vec<String>),
+    Binary(u64, u64),
+    TooLarge(u64, u64),
+    TypeMismatch,
+}
+
+/// Fills `buf` as far as the reader allows, returning the bytes read.
+fn read_chunk(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(filled)
+}
+
+/// Whether both files have the same content, comparing sizes first and
+/// then streaming both in chunks.
+pub fn same_content(a: &Path, b: &Path) -> io::Result<bool> {
+    let (mut fa, mut fb) = (fs::File::open(a)?, fs::File::open(b)?);
+    if fa.metadata()?.len() != fb.metadata()?.len() {
+        return Ok(false);
+    }
+    let (mut ba, mut bb) = (vec![0; CHUNK_SIZE], vec![0; CHUNK_SIZE]);
+    loop {
+        let na = read_chunk(&mut fa, &mut ba)?;
+        let nb = read_chunk(&mut fb, &mut bb)?;
+        if ba[..na] != bb[..nb] {
+            return Ok(false);
+        }
+        if na == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+/// Whether the file has a NUL byte near its start.
+pub fn is_binary(path: &Path) -> io::Result<bool> {
+    let mut buf = vec![0; SNIFF_SIZE];
+    let n = read_chunk(&mut fs::File::open(path)?, &mut buf)?;
+    Ok(buf[..n].contains(&0))
+}
+
+fn read_lines(path: &Path) -> io::Result<Vec<String>> {
+    let bytes = fs::read(path)?;
+    Ok(String::from_utf8_lossy(&bytes)
+        .lines()
+        .map(String::from)
+        .collect())
+}
+
+/// Compares `source` with `target`, loading them as text only when both are
+/// text files of at most `max_size` bytes.
+pub fn compare(source: &Path, target: &Path, max_size: u64) -> io::Result<Comparison> {
+    if same_content(source, target)? {
+        return Ok(Comparison::Same);
+    }
+    let sizes = (fs::metadata(source)?.len(), fs::metadata(target)?.len());
+    Ok(match (is_binary(source)?, is_binary(target)?) {
+        (true, true) => Comparison::Binary(sizes.0, sizes.1),
+        (false, false) if sizes.0.max(sizes.1) > max_size => Comparison::TooLarge(sizes.0, sizes.1),
+        (false, false) => Comparison::Text(read_lines(source)?, read_lines(target)?),
+        _ => Comparison::TypeMismatch,
+    })
+}
+
+/// Differences between two directory trees, as paths relative to them.
+/// Directories only on one side are listed without their contents.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct DirComparison {
+    pub only_source: Vec<PathBuf>,
+    pub only_target: Vec<PathBuf>,
+    pub differing: Vec<PathBuf>,
+}
+
+fn file_types(dir: &Path) -> io::Result<BTreeMap<OsString, fs::FileType>> {
+    fs::read_dir(dir)?
+        .map(|entry| {
+            let entry = entry?;
+            Ok((entry.file_name(), entry.file_type()?))
+        })
+        .collect()
+}
+
+fn compare_trees(
+    source: &Path,
+    target: &Path,
+    rel: &Path,
+    out: &mut DirComparison,
+) -> io::Result<()> {
+    let sources = file_types(&source.join(rel))?;
+    let targets = file_types(&target.join(rel))?;
+    let names = sources
+        .keys()
+        .chain(targets.keys())
+        .collect::<BTreeSet<_>>();
+    for name in names {
+        let path = rel.join(name);
+        match (sources.get(name), targets.get(name)) {
+            (Some(s), Some(t)) if s.is_dir() && t.is_dir() => {
+                compare_trees(source, target, &path, out)?
+            }
+            (Some(s), Some(t)) if s.is_dir() || t.is_dir() => out.differing.push(path),
+            (Some(_), Some(_)) => {
+                if !same_content(&source.join(&path), &target.join(&path))? {
+                    out.differing.push(path)
+                }
+            }
+            (Some(_), None) => out.only_source.push(path),
+            (None, _) => out.only_target.push(path),
+        }
+    }
+    Ok(())
+}
+
+/// Compares the trees below `source` and `target` recursively.
+pub fn compare_dirs(source: &Path, target: &Path) -> io::Result<DirComparison> {
+    let mut comparison = DirComparison::default();
+    compare_trees(source, target, Path::new(""), &mut comparison)?;
+    Ok(comparison)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_files(name: &str, a: &[u8], b: &[u8]) -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("wagon-compare-{name}-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (pa, pb) = (dir.join("a"), dir.join("b"));
+        fs::write(&pa, a).unwrap();
+        fs::write(&pb, b).unwrap();
+        (pa, pb)
+    }
+
+    fn compare_bytes(name: &str, a: &[u8], b: &[u8], max_size: u64) -> Comparison {
+        let (pa, pb) = write_files(name, a, b);
+        let comparison = compare(&pa, &pb, max_size).unwrap();
+        let _ = fs::remove_dir_all(pa.parent().unwrap());
+        comparison
+    }
+
+    #[test]
+    fn same_content_streams_past_the_first_chunk() -> io::Result<()> {
+        let a = vec![b'x'; CHUNK_SIZE * 2 + 10];
+        let mut b = a.clone();
+        b[CHUNK_SIZE + 5] = b'y';
+        let (pa, pb) = write_files("chunks", &a, &b);
+        let differ = !same_content(&pa, &pb)?;
+        let same = same_content(&pa, &pa)?;
+        let _ = fs::remove_dir_all(pa.parent().unwrap());
+        assert!(differ && same);
+        Ok(())
+    }
+
+    #[test]
+    fn compares_directory_trees() -> io::Result<()> {
+        let dir = std::env::temp_dir().join(format!("wagon-compare-dirs-{}", std::process::id()));
+        let (source, target) = (dir.join("source"), dir.join("target"));
+        for (path, content) in [
+            ("source/same", "same"),
+            ("target/same", "same"),
+            ("source/sub/changed", "a"),
+            ("target/sub/changed", "b"),
+            ("source/sub/new", "new"),
+            ("target/local/cache", "x"),
+            ("source/kind", "file"),
+            ("target/kind/file", "dir"),
+        ] {
+            fs::create_dir_all(dir.join(path).parent().unwrap())?;
+            fs::write(dir.join(path), content)?;
+        }
+        let comparison = compare_dirs(&source, &target);
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(
+            comparison?,
+            DirComparison {
+                only_source: vec![PathBuf::from("sub/new")],
+                only_target: vec![PathBuf::from("local")],
+                differing: vec![PathBuf::from("kind"), PathBuf::from("sub/changed")],
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn detects_binary_by_nul_bytes() {
+        assert_eq!(
+            compare_bytes("binary", b"\x00\x01\x02", b"\x00\x01", 1024),
+            Comparison::Binary(3, 2)
+        );
+        assert_eq!(
+            compare_bytes("mismatch", b"text\n", b"\x00bin", 1024),
+            Comparison::TypeMismatch
+        );
+        assert_eq!(
+            compare_bytes("latin1", b"caf\xe9\n", b"cafe\n", 1024),
+            Comparison::Text(vec!["caf\u{fffd}".to_owned()], vec!["cafe".to_owned()])
+        );
+    }
+
+    #[test]
+    fn loads_text_only_under_the_size_limit() {
+        assert_eq!(
+            compare_bytes("same", b"a\nb\n", b"a\nb\n", 1),
+            Comparison::Same
+        );
+        assert_eq!(
+            compare_bytes("large", b"a\nb\n", b"a\n", 3),
+            Comparison::TooLarge(4, 2)
+        );
+    }
+}
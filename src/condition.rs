@@ -0,0 +1,183 @@
+use crate::platform::{distro_ids, find_executable, os_release};
+use glob::Pattern;
+use serde::Deserialize;
+use std::env::{self, consts};
+use std::ffi::CStr;
+use std::sync::OnceLock;
+
+/// A condition on the running system. All given fields have to match.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Condition {
+    /// `std::env::consts::OS`, e.g. `linux` or `macos`.
+    pub os: Option<String>,
+    /// `std::env::consts::ARCH`, e.g. `x86_64` or `aarch64`.
+    pub arch: Option<String>,
+    /// `ID` or one of `ID_LIKE` in `/etc/os-release`.
+    pub distro: Option<String>,
+    /// Glob matching `VERSION_ID` in `/etc/os-release`.
+    pub distro_version: Option<String>,
+    /// Glob matching the hostname.
+    pub hostname: Option<String>,
+    /// Name of an executable that has to be on `PATH`.
+    pub executable: Option<String>,
+    /// `VAR` (set and not empty) or `VAR=value` entries.
+    pub env: Option<Vec<String>>,
+    pub all: Option<Vec<Condition>>,
+    pub any: Option<Vec<Condition>>,
+    pub not: Option<Box<Condition>>,
+}
+
+/// Facts about the running system that conditions are evaluated against.
+pub struct Facts {
+    pub os: String,
+    pub arch: String,
+    pub distro: Vec<String>,
+    pub distro_version: Option<String>,
+    pub hostname: String,
+    pub env: fn(&str) -> Option<String>,
+    pub executable: fn(&str) -> bool,
+}
+
+fn hostname() -> String {
+    let mut buf = [0 as libc::c_char; 256];
+    if unsafe { libc::gethostname(buf.as_mut_ptr(), buf.len()) } != 0 {
+        return String::new();
+    }
+    buf[buf.len() - 1] = 0;
+    unsafe { CStr::from_ptr(buf.as_ptr()) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+impl Facts {
+    pub fn current() -> &'static Facts {
+        static FACTS: OnceLock<Facts> = OnceLock::new();
+        FACTS.get_or_init(|| Facts {
+            os: consts::OS.to_owned(),
+            arch: consts::ARCH.to_owned(),
+            distro: distro_ids(),
+            distro_version: os_release().remove("VERSION_ID"),
+            hostname: hostname(),
+            env: |name| env::var(name).ok(),
+            executable: |name| find_executable(name).is_some(),
+        })
+    }
+}
+
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    Pattern::new(pattern).map_or(pattern == text, |p| p.matches(text))
+}
+
+impl Condition {
+    pub fn os(os: &str) -> Self {
+        Self {
+            os: Some(os.to_owned()),
+            ..Self::default()
+        }
+    }
+
+    pub fn matches(&self) -> bool {
+        self.matches_facts(Facts::current())
+    }
+
+    pub fn matches_facts(&self, facts: &Facts) -> bool {
+        self.os.as_ref().is_none_or(|os| *os == facts.os)
+            && self.arch.as_ref().is_none_or(|arch| *arch == facts.arch)
+            && self
+                .distro
+                .as_ref()
+                .is_none_or(|distro| facts.distro.contains(distro))
+            && self.distro_version.as_ref().is_none_or(|version| {
+                facts
+                    .distro_version
+                    .as_ref()
+                    .is_some_and(|v| glob_matches(version, v))
+            })
+            && self
+                .hostname
+                .as_ref()
+                .is_none_or(|host| glob_matches(host, &facts.hostname))
+            && self
+                .executable
+                .as_ref()
+                .is_none_or(|name| (facts.executable)(name))
+            && self
+                .env
+                .iter()
+                .flatten()
+                .all(|entry| match entry.split_once('=') {
+                    Some((name, value)) => (facts.env)(name).is_some_and(|v| v == value),
+                    None => (facts.env)(entry).is_some_and(|v| !v.is_empty()),
+                })
+            && self.all.iter().flatten().all(|c| c.matches_facts(facts))
+            && self
+                .any
+                .as_ref()
+                .is_none_or(|any| any.iter().any(|c| c.matches_facts(facts)))
+            && self.not.as_ref().is_none_or(|c| !c.matches_facts(facts))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn facts() -> Facts {
+        Facts {
+            os: "linux".to_owned(),
+            arch: "aarch64".to_owned(),
+            distro: vec!["ubuntu".to_owned(), "debian".to_owned()],
+            distro_version: Some("24.04".to_owned()),
+            hostname: "work-laptop".to_owned(),
+            env: |name| (name == "WAGON_PROFILE").then(|| "work".to_owned()),
+            executable: |name| name == "brew",
+        }
+    }
+
+    fn matches(condition: &str) -> bool {
+        toml::from_str::<Condition>(condition)
+            .unwrap()
+            .matches_facts(&facts())
+    }
+
+    #[test]
+    fn fields_must_all_match() {
+        assert!(matches(""));
+        assert!(matches(r#"os = "linux""#));
+        assert!(matches(
+            r#"os = "linux"
+            arch = "aarch64"
+            distro = "debian"
+            distro_version = "24.*"
+            hostname = "work-*"
+            executable = "brew"
+            env = ["WAGON_PROFILE=work"]"#
+        ));
+        assert!(!matches(
+            r#"os = "linux"
+            arch = "x86_64""#
+        ));
+        assert!(!matches(r#"distro = "fedora""#));
+        assert!(!matches(r#"hostname = "home-*""#));
+        assert!(!matches(r#"executable = "apt""#));
+        assert!(!matches(r#"env = ["HOME"]"#));
+    }
+
+    #[test]
+    fn conditions_combine_with_all_any_not() {
+        assert!(matches(
+            r#"any = [{ os = "macos" }, { distro = "debian" }]"#
+        ));
+        assert!(!matches(r#"any = [{ os = "macos" }, { distro = "arch" }]"#));
+        assert!(matches(r#"not = { os = "macos" }"#));
+        assert!(!matches(
+            r#"all = [{ os = "linux" }, { not = { arch = "aarch64" } }]"#
+        ));
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        assert!(toml::from_str::<Condition>(r#"platform = "linux""#).is_err());
+    }
+}
@@ -1,57 +1,360 @@
 use crate::CONFFILE_NAME;
-use anyhow::Result;
+use crate::condition::Condition;
+use anyhow::{Context, Result, anyhow, bail};
 use glob::glob;
 use serde::Deserialize;
-use std::env::consts;
+use std::collections::BTreeMap;
 use std::fs;
 use std::io::Read;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 const XDG_CONFIG_HOME: &str = "XDG_CONFIG_HOME";
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Deserialize, Debug)]
+#[serde(default)]
 pub struct GlobalConfig {
+    /// Root of cloned repositories, relative to the home directory.
     pub src: PathBuf,
+    /// Protocol of the URLs built from shorthands and `owner/repo`.
+    pub protocol: Protocol,
+    /// Shorthand prefixes, e.g. `work = "git.example.com"` for `work:team/repo`.
+    pub shorthands: BTreeMap<String, String>,
+    /// Repositories kept in sync by `repo sync`.
+    pub repos: Vec<String>,
+    /// File listing more repositories, one per line, relative to this config.
+    pub repos_file: Option<PathBuf>,
+    /// Defaults for the options of `repo` clones.
+    pub clone: CloneOptions,
+    /// Hooks run in every fresh clone.
+    pub post_clone: Vec<Command>,
+    /// Command template used by `wget`, with `{url}`, `{dest}`, `{root}` and
+    /// `{level}` placeholders.
+    pub fetcher: Option<String>,
+    /// External diff command used by `list`, e.g. `delta` or `difft`.
+    pub diff_tool: Option<String>,
+    /// Lines of context of the diffs shown by `list`.
+    pub diff_context: Option<usize>,
+    /// Largest file in bytes loaded for the diffs shown by `list`.
+    pub max_diff_size: Option<u64>,
+    /// Directory of the backups made by `link` and `copy`, relative to this
+    /// config, `backups` in the state directory when unset.
+    pub backup_dir: Option<PathBuf>,
+}
+
+/// Options of `git clone`, each unset one falling back to the global config.
+#[derive(Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct CloneOptions {
+    pub depth: Option<u32>,
+    pub branch: Option<String>,
+    pub recurse_submodules: Option<bool>,
+    pub layout: Option<Layout>,
+}
+
+impl CloneOptions {
+    pub fn or(self, defaults: &CloneOptions) -> CloneOptions {
+        CloneOptions {
+            depth: self.depth.or(defaults.depth),
+            branch: self.branch.or_else(|| defaults.branch.clone()),
+            recurse_submodules: self.recurse_submodules.or(defaults.recurse_submodules),
+            layout: self.layout.or(defaults.layout),
+        }
+    }
+}
+
+/// How a clone is laid out on disk.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Layout {
+    /// A regular clone with a working tree.
+    #[default]
+    Normal,
+    /// A bare repository without a working tree.
+    Bare,
+    /// A bare repository in `.bare` with a worktree per branch next to it.
+    Worktree,
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    #[default]
+    Https,
+    Ssh,
+}
+
+impl Default for GlobalConfig {
+    fn default() -> Self {
+        Self {
+            src: PathBuf::from("src"),
+            protocol: Protocol::default(),
+            shorthands: BTreeMap::new(),
+            repos: vec![],
+            repos_file: None,
+            clone: CloneOptions::default(),
+            post_clone: vec![],
+            fetcher: None,
+            diff_tool: None,
+            diff_context: None,
+            max_diff_size: None,
+            backup_dir: None,
+        }
+    }
 }
 
 impl GlobalConfig {
-    pub fn new() -> Self {
+    pub fn new() -> Result<Self> {
         let path = Self::get_path();
-        if let Ok(mut file) = fs::File::open(path) {
+        let mut config = if let Ok(mut file) = fs::File::open(&path) {
             let mut buf = String::default();
-            file.read_to_string(&mut buf).unwrap_or_default();
-            toml::from_str::<GlobalConfig>(&buf).unwrap_or_default()
+            file.read_to_string(&mut buf)?;
+            toml::from_str::<GlobalConfig>(&buf)
+                .with_context(|| format!("invalid config: {}", path.display()))?
         } else {
-            Self {
-                src: PathBuf::from("src"),
-            }
+            Self::default()
+        };
+        let home = dirs::home_dir().context("cant get home dir")?;
+        config.src = expand_path(&config.src, &home)
+            .with_context(|| format!("cannot expand `src` in {}", path.display()))?;
+        let dir = path.parent().unwrap_or(&home);
+        if let Some(file) = &config.repos_file {
+            config.repos_file =
+                Some(expand_path(file, dir).with_context(|| {
+                    format!("cannot expand `repos_file` in {}", path.display())
+                })?);
+        }
+        if let Some(backups) = &config.backup_dir {
+            config.backup_dir =
+                Some(expand_path(backups, dir).with_context(|| {
+                    format!("cannot expand `backup_dir` in {}", path.display())
+                })?);
         }
+        Ok(config)
     }
 
-    fn get_path() -> PathBuf {
+    /// Directory of the backups made by `link` and `copy`.
+    pub fn backup_root(&self) -> Result<PathBuf> {
+        match &self.backup_dir {
+            Some(dir) => Ok(dir.clone()),
+            None => Ok(state_dir().context("cant get state dir")?.join("backups")),
+        }
+    }
+
+    /// Repositories from `repos` followed by those listed in `repos_file`.
+    pub fn repo_list(&self) -> Result<Vec<String>> {
+        let mut repos = self.repos.clone();
+        if let Some(file) = &self.repos_file {
+            let text = fs::read_to_string(file)
+                .with_context(|| format!("cannot read {}", file.display()))?;
+            repos.extend(parse_repo_list(&text));
+        }
+        Ok(repos)
+    }
+
+    fn dir() -> PathBuf {
         let mut default_home = dirs::home_dir().unwrap_or_else(|| PathBuf::from("/"));
         default_home.push(".config");
         let mut path = std::env::var(XDG_CONFIG_HOME).map_or_else(|_| default_home, PathBuf::from);
         path.push("wagon");
-        path.push("config.toml");
         path
     }
+
+    fn get_path() -> PathBuf {
+        Self::dir().join("config.toml")
+    }
+
+    /// Ignore file applied to every processed directory, in gitignore syntax.
+    pub fn ignore_path() -> PathBuf {
+        Self::dir().join("ignore")
+    }
 }
 
-#[derive(Deserialize, Debug)]
+/// Parses a repository list: one repository per line, `#` starts a comment.
+pub fn parse_repo_list(text: &str) -> Vec<String> {
+    text.lines()
+        .map(|line| line.split_once('#').map_or(line, |(l, _)| l).trim())
+        .filter(|line| !line.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Directory for state kept between runs, such as the init record.
+pub fn state_dir() -> Option<PathBuf> {
+    let mut path = dirs::state_dir().or_else(dirs::data_local_dir)?;
+    path.push("wagon");
+    Some(path)
+}
+
+#[derive(Deserialize, Debug, Default)]
 pub struct Config {
+    /// Path of the `.wagon.toml` this config was read from.
+    #[serde(skip)]
+    pub path: PathBuf,
     pub dest: Option<PathBuf>,
     pub init: Option<Vec<Command>>,
     pub update: Option<Vec<Command>>,
+    pub pre_link: Option<Vec<Command>>,
+    pub post_link: Option<Vec<Command>>,
+    pub pre_unlink: Option<Vec<Command>>,
+    pub post_unlink: Option<Vec<Command>>,
+    pub post_copy: Option<Vec<Command>>,
     pub dirs: Option<Vec<PathBuf>>,
     pub os: Option<String>,
+    /// Condition for the config to be chosen, next to `os`.
+    pub when: Option<Condition>,
+    /// Files only managed when their condition matches.
+    pub files: Option<Vec<FileRule>>,
+    pub dotted_names: Option<bool>,
+    /// Files not to manage.
+    pub ignore: Option<Vec<GlobRule>>,
+    /// Files to manage even when ignored by a built-in name, an `ignore`
+    /// glob, a `.wagonignore` or the global ignore file. Files hidden by
+    /// `.gitignore` or `.ignore` files, or inside an ignored directory, are
+    /// not walked and stay unmanaged.
+    pub include: Option<Vec<GlobRule>>,
+    /// Whether `.gitignore`, `.git/info/exclude` and the global git
+    /// excludes file hide files, `true` when unset.
+    pub respect_gitignore: Option<bool>,
+    /// Ignore files honored, all when unset.
+    pub ignore_sources: Option<Vec<IgnoreSource>>,
+    /// Directories whose hooks run before the hooks of this one.
+    pub depends: Option<Vec<PathBuf>>,
+    pub packages: Option<Packages>,
 }
 
+/// Restricts the files matching `path`, a glob relative to the directory
+/// being processed, to systems matching `when`.
 #[derive(Deserialize, Debug)]
+pub struct FileRule {
+    pub path: String,
+    pub when: Condition,
+}
+
+/// A kind of ignore file that can hide files below a directory.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum IgnoreSource {
+    /// `.gitignore` and `.git/info/exclude` in a git repository.
+    Git,
+    /// The global excludes file of git, `core.excludesFile`.
+    GlobalGit,
+    /// `.ignore` files, as used by ripgrep and fd.
+    Ignore,
+    /// `.wagonignore` files.
+    Wagonignore,
+}
+
+/// A glob relative to the directory being processed, either applying
+/// everywhere or only on systems matching a condition.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum GlobRule {
+    Always(String),
+    When(Box<FileRule>),
+}
+
+impl GlobRule {
+    /// The glob when the rule applies to the running system.
+    pub fn active(&self) -> Option<&str> {
+        match self {
+            GlobRule::Always(glob) => Some(glob),
+            GlobRule::When(rule) => rule.when.matches().then_some(&rule.path),
+        }
+    }
+}
+
+fn os_and_when_match(os: Option<&str>, when: Option<&Condition>) -> bool {
+    os.is_none_or(|os| Condition::os(os).matches()) && when.is_none_or(Condition::matches)
+}
+
+impl Command {
+    /// Whether the hook applies to the running system.
+    pub fn applies(&self) -> bool {
+        os_and_when_match(self.os.as_deref(), self.when.as_ref())
+    }
+}
+
+/// Packages installed by `init` with the package manager of the current system.
+#[derive(Deserialize, Debug, Default)]
+pub struct Packages {
+    pub brew: Option<Vec<String>>,
+    pub apt: Option<Vec<String>>,
+    pub dnf: Option<Vec<String>>,
+    pub pacman: Option<Vec<String>>,
+    pub cargo: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Debug, Default)]
 pub struct Command {
+    /// Name other hooks can refer to in `after`.
+    pub name: Option<String>,
+    /// Names of hooks, in any of the processed configs, to run before this one.
+    pub after: Option<Vec<String>>,
     pub command: String,
+    #[serde(default)]
     pub args: Vec<String>,
     pub os: Option<String>,
+    pub when: Option<Condition>,
+    /// Run `command` as a script through `sh -c`, with `args` as positional parameters.
+    pub shell: Option<bool>,
+    /// Working directory, relative to the config's directory (the default).
+    pub cwd: Option<PathBuf>,
+    pub env: Option<BTreeMap<String, String>>,
+    /// Keep running the remaining hooks when this one fails.
+    pub continue_on_error: Option<bool>,
+    /// Skip the hook when this path exists.
+    pub creates: Option<PathBuf>,
+    /// Skip the hook when this command succeeds.
+    pub unless: Option<Vec<String>>,
+    /// Rerun a recorded hook when files matching these globs change.
+    pub onchange: Option<Vec<String>>,
+    /// Kill the hook when it runs longer, in seconds or with an `s`/`m`/`h`/`d`
+    /// suffix.
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub timeout: Option<Duration>,
+}
+
+/// Parses durations such as `90`, `30s`, `5m`, `2h` or `30d`.
+pub fn parse_duration(text: &str) -> Result<Duration> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number = number
+        .parse::<u64>()
+        .with_context(|| format!("invalid duration `{text}`"))?;
+    let unit = match unit.trim() {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => bail!("invalid duration unit in `{text}`"),
+    };
+    let secs = number
+        .checked_mul(unit)
+        .with_context(|| format!("invalid duration `{text}`"))?;
+    Ok(Duration::from_secs(secs))
+}
+
+fn deserialize_duration<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Raw {
+        Seconds(u64),
+        Text(String),
+    }
+    match Option::<Raw>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(Raw::Seconds(seconds)) => Ok(Some(Duration::from_secs(seconds))),
+        Some(Raw::Text(text)) => parse_duration(&text)
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+    }
 }
 
 impl Config {
@@ -59,31 +362,252 @@ impl Config {
         let mut file = fs::File::open(confpath)?;
         let mut buf = String::default();
         file.read_to_string(&mut buf)?;
-        Ok(toml::from_str::<Config>(&buf)?)
+        let mut config = toml::from_str::<Config>(&buf)?;
+        config.path = confpath.to_path_buf();
+        Ok(config)
+    }
+
+    /// Whether the config applies to the running system.
+    pub fn applies(&self) -> bool {
+        os_and_when_match(self.os.as_deref(), self.when.as_ref())
+    }
+
+    /// Whether the file at `path`, relative to the processed directory, is
+    /// managed on the running system.
+    pub fn manages(&self, path: &Path) -> bool {
+        self.files.iter().flatten().all(|rule| {
+            let matched = glob::Pattern::new(&rule.path).is_ok_and(|p| p.matches_path(path));
+            !matched || rule.when.matches()
+        })
+    }
+
+    /// Whether files ignored by `source` are hidden.
+    pub fn honors(&self, source: IgnoreSource) -> bool {
+        let git = matches!(source, IgnoreSource::Git | IgnoreSource::GlobalGit);
+        if git && self.respect_gitignore == Some(false) {
+            return false;
+        }
+        self.ignore_sources
+            .as_ref()
+            .is_none_or(|sources| sources.contains(&source))
+    }
+
+    /// Directory containing the config file, against which relative paths are resolved.
+    pub fn dir(&self) -> &Path {
+        self.path.parent().unwrap_or_else(|| Path::new(""))
+    }
+
+    fn resolve_paths(mut self) -> Result<Self> {
+        let dir = self.dir().to_path_buf();
+        let context = |field: &str| format!("cannot expand `{field}` in {}", self.path.display());
+        if let Some(dest) = &self.dest {
+            self.dest = Some(expand_path(dest, &dir).with_context(|| context("dest"))?);
+        }
+        if let Some(dirs) = &self.dirs {
+            let dirs = dirs
+                .iter()
+                .map(|d| expand_path(d, &dir))
+                .collect::<Result<_>>()
+                .with_context(|| context("dirs"))?;
+            self.dirs = Some(dirs);
+        }
+        if let Some(depends) = &self.depends {
+            let depends = depends
+                .iter()
+                .map(|d| expand_path(d, &dir))
+                .collect::<Result<_>>()
+                .with_context(|| context("depends"))?;
+            self.depends = Some(depends);
+        }
+        Ok(self)
+    }
+}
+
+fn lookup_env(name: &str) -> Option<String> {
+    std::env::var(name).ok()
+}
+
+/// Expands `~`, `$VAR`, `${VAR}` and `${VAR:-default}` in `raw` and resolves
+/// the result against `base` when it is relative.
+pub fn expand_path(raw: &Path, base: &Path) -> Result<PathBuf> {
+    let Some(raw) = raw.to_str() else {
+        return Ok(base.join(raw));
+    };
+    let home = dirs::home_dir().unwrap_or_default();
+    Ok(base.join(expand_str(raw, &lookup_env, &home)?))
+}
+
+fn expand_str(raw: &str, lookup: &dyn Fn(&str) -> Option<String>, home: &Path) -> Result<String> {
+    let expanded = expand_vars(raw, lookup, home)?;
+    Ok(if expanded == "~" {
+        home.to_string_lossy().into_owned()
+    } else if let Some(rest) = expanded.strip_prefix("~/") {
+        home.join(rest).to_string_lossy().into_owned()
+    } else {
+        expanded
+    })
+}
+
+fn expand_vars(raw: &str, lookup: &dyn Fn(&str) -> Option<String>, home: &Path) -> Result<String> {
+    let mut out = String::with_capacity(raw.len());
+    let mut chars = raw.char_indices().peekable();
+    while let Some((_, ch)) = chars.next() {
+        if ch != '$' {
+            out.push(ch);
+            continue;
+        }
+        match chars.peek().copied() {
+            Some((_, '$')) => {
+                chars.next();
+                out.push('$');
+            }
+            Some((start, '{')) => {
+                chars.next();
+                let mut depth = 1;
+                let mut end = None;
+                for (i, c) in chars.by_ref() {
+                    match c {
+                        '{' => depth += 1,
+                        '}' => {
+                            depth -= 1;
+                            if depth == 0 {
+                                end = Some(i);
+                                break;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                let end = end.ok_or_else(|| anyhow!("unterminated `${{` in `{raw}`"))?;
+                let expr = &raw[start + 1..end];
+                match expr.split_once(":-") {
+                    Some((name, default)) => match lookup(name) {
+                        Some(value) if !value.is_empty() => out.push_str(&value),
+                        _ => out.push_str(&expand_str(default, lookup, home)?),
+                    },
+                    None => out.push_str(&lookup_var(expr, lookup)?),
+                }
+            }
+            Some((start, c)) if c == '_' || c.is_ascii_alphabetic() => {
+                let mut end = raw.len();
+                while let Some(&(i, c)) = chars.peek() {
+                    if c == '_' || c.is_ascii_alphanumeric() {
+                        chars.next();
+                    } else {
+                        end = i;
+                        break;
+                    }
+                }
+                out.push_str(&lookup_var(&raw[start..end], lookup)?);
+            }
+            _ => out.push('$'),
+        }
     }
+    Ok(out)
+}
+
+fn lookup_var(name: &str, lookup: &dyn Fn(&str) -> Option<String>) -> Result<String> {
+    if name.is_empty() {
+        bail!("empty variable name");
+    }
+    lookup(name).ok_or_else(|| anyhow!("undefined environment variable `{name}`"))
 }
 
 pub fn get_config(base: &Path) -> Result<Option<Config>> {
+    find_config(base, &mut |_, _| {})
+}
+
+/// Why a config file is not chosen, or `None` when it applies.
+fn skip_reason(config: &Config) -> Option<String> {
+    if config.applies() {
+        return None;
+    }
+    Some(match &config.os {
+        Some(os) if !Condition::os(os).matches() => format!("`os = {os:?}` does not match"),
+        _ => "`when` does not match".to_owned(),
+    })
+}
+
+/// Suffixes of editor, backup and merge leftovers of a config file.
+const LEFTOVER_SUFFIXES: [&str; 8] = ["bak", "old", "orig", "rej", "save", "swo", "swp", "tmp"];
+
+/// Whether `name` is `.wagon.toml` or `.wagon.toml.<suffix>`, rather than a
+/// leftover such as `.wagon.toml~` or `.wagon.toml.orig`.
+fn is_config_name(name: &str) -> bool {
+    let Some(rest) = name.strip_prefix(CONFFILE_NAME) else {
+        return false;
+    };
+    let Some(suffix) = rest.strip_prefix('.') else {
+        return rest.is_empty();
+    };
+    !suffix.is_empty()
+        && suffix
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        && !LEFTOVER_SUFFIXES.contains(&suffix.to_ascii_lowercase().as_str())
+}
+
+/// Like [`get_config`], calling `skipped` with each config file passed over
+/// because its condition does not match. A config file that does not parse
+/// is an error rather than skipped, while leftovers such as `.wagon.toml~`
+/// are warned about and skipped.
+pub fn find_config(base: &Path, skipped: &mut dyn FnMut(&Path, String)) -> Result<Option<Config>> {
     let longest = base.join(Path::new(CONFFILE_NAME));
     let mut components = longest.components();
     while components.next_back().is_some() {
         let compstr = components.as_path().to_str().unwrap_or_default();
         let confpat = format!("{compstr}/{CONFFILE_NAME}*");
         for confpath in glob(&confpat)?.flatten() {
-            if let Ok(config) = Config::from_path(&confpath) {
-                if let Some(os) = &config.os {
-                    if os == consts::OS {
-                        return Ok(Some(config));
-                    }
-                } else {
-                    return Ok(Some(config));
-                }
+            let name = confpath.file_name().unwrap_or_default();
+            if !is_config_name(&name.to_string_lossy()) {
+                tracing::warn!("ignoring {}: not a config file name", confpath.display());
+                continue;
+            }
+            let config = Config::from_path(&confpath)
+                .with_context(|| format!("invalid config: {}", confpath.display()))?;
+            match skip_reason(&config) {
+                Some(reason) => skipped(&confpath, reason),
+                None => return Ok(Some(config.resolve_paths()?)),
             }
         }
     }
     Ok(None)
 }
 
+#[test]
+fn get_config_rejects_invalid_conditions() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("wagon-invalid-config-{}", std::process::id()));
+    fs::create_dir_all(&dir)?;
+    fs::write(dir.join(CONFFILE_NAME), "when = { oss = \"linux\" }\n")?;
+    let result = get_config(&dir);
+    let _ = fs::remove_dir_all(&dir);
+    let err = result.expect_err("invalid config is an error");
+    assert!(err.to_string().contains("invalid config"), "{err:#}");
+    Ok(())
+}
+
+#[test]
+fn get_config_skips_leftover_config_files() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("wagon-leftover-config-{}", std::process::id()));
+    let sub = dir.join("sub");
+    fs::create_dir_all(&sub)?;
+    fs::write(sub.join(CONFFILE_NAME), "os = \"plan9\"\n")?;
+    // Leftovers sort after the skipped config, so each of them is reached.
+    for leftover in ["~", ".orig", ".bak", ".swp", "x"] {
+        fs::write(
+            sub.join(format!("{CONFFILE_NAME}{leftover}")),
+            "<<<<<<< HEAD\n",
+        )?;
+    }
+    fs::write(dir.join(CONFFILE_NAME), "dotted_names = true\n")?;
+    let result = get_config(&sub);
+    let _ = fs::remove_dir_all(&dir);
+    assert_eq!(result?.and_then(|c| c.dotted_names), Some(true));
+    assert!(is_config_name(".wagon.toml") && is_config_name(".wagon.toml.linux"));
+    assert!(!is_config_name(".wagon.toml.") && !is_config_name(".wagon.toml.ORIG"));
+    Ok(())
+}
+
 #[test]
 fn test_get_config() -> Result<()> {
     let test_base = PathBuf::from("test/repo/bash");
@@ -92,3 +616,140 @@ fn test_get_config() -> Result<()> {
     assert!(config.is_some());
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "XDG_CONFIG_HOME" => Some("/xdg".to_owned()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    fn expand(raw: &str) -> Result<String> {
+        expand_str(raw, &lookup, Path::new("/home/user"))
+    }
+
+    #[test]
+    fn expands_tilde_and_variables() -> Result<()> {
+        assert_eq!(expand("~")?, "/home/user");
+        assert_eq!(expand("~/.config")?, "/home/user/.config");
+        assert_eq!(expand("$XDG_CONFIG_HOME/git")?, "/xdg/git");
+        assert_eq!(expand("${XDG_CONFIG_HOME}git")?, "/xdggit");
+        assert_eq!(expand("cost$$")?, "cost$");
+        Ok(())
+    }
+
+    #[test]
+    fn expands_defaults_for_unset_or_empty_variables() -> Result<()> {
+        assert_eq!(
+            expand("${XDG_DATA_HOME:-~/.local/share}")?,
+            "/home/user/.local/share"
+        );
+        assert_eq!(expand("${EMPTY:-fallback}")?, "fallback");
+        assert_eq!(expand("${XDG_CONFIG_HOME:-~/.config}")?, "/xdg");
+        Ok(())
+    }
+
+    #[test]
+    fn undefined_variables_are_errors() {
+        let err = expand("$XDG_DATA_HOME/wagon").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "undefined environment variable `XDG_DATA_HOME`"
+        );
+        assert!(expand("${UNTERMINATED").is_err());
+    }
+
+    #[test]
+    fn parses_durations() -> Result<()> {
+        assert_eq!(parse_duration("90")?, Duration::from_secs(90));
+        assert_eq!(parse_duration("5m")?, Duration::from_secs(300));
+        assert_eq!(parse_duration("30d")?, Duration::from_secs(30 * 86400));
+        assert!(parse_duration("5 weeks").is_err());
+        assert!(parse_duration("999999999999999999d").is_err());
+        assert!(
+            toml::from_str::<Command>("command = \"git\"\ntimeout = \"999999999999999999d\"")
+                .is_err()
+        );
+        let hook = toml::from_str::<Command>("command = \"git\"\ntimeout = \"2h\"")?;
+        assert_eq!(hook.timeout, Some(Duration::from_secs(7200)));
+        Ok(())
+    }
+
+    #[test]
+    fn ignore_sources_default_to_all() {
+        let conf = |text| toml::from_str::<Config>(text).unwrap();
+        let all = conf("");
+        let no_git = conf("respect_gitignore = false\n");
+        let only = conf("ignore_sources = [\"global_git\", \"wagonignore\"]\n");
+        assert!(all.honors(IgnoreSource::Git) && all.honors(IgnoreSource::Wagonignore));
+        assert!(!no_git.honors(IgnoreSource::Git) && !no_git.honors(IgnoreSource::GlobalGit));
+        assert!(no_git.honors(IgnoreSource::Ignore));
+        assert!(only.honors(IgnoreSource::GlobalGit) && !only.honors(IgnoreSource::Git));
+        assert!(!only.honors(IgnoreSource::Ignore));
+    }
+
+    #[test]
+    fn clone_options_fall_back_to_defaults() -> Result<()> {
+        let conf = toml::from_str::<GlobalConfig>(
+            r#"
+            [clone]
+            depth = 1
+            layout = "worktree"
+            "#,
+        )?;
+        let options = CloneOptions {
+            branch: Some("dev".to_owned()),
+            layout: Some(Layout::Bare),
+            ..CloneOptions::default()
+        };
+        assert_eq!(
+            options.or(&conf.clone),
+            CloneOptions {
+                depth: Some(1),
+                branch: Some("dev".to_owned()),
+                recurse_submodules: None,
+                layout: Some(Layout::Bare),
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn repo_lists_skip_comments_and_blank_lines() {
+        let text = "# dotfiles\nowner/repo\n\n  work:team/tool  # pinned\n";
+        assert_eq!(parse_repo_list(text), ["owner/repo", "work:team/tool"]);
+    }
+
+    #[test]
+    fn file_rules_restrict_matching_files() {
+        let config = toml::from_str::<Config>(
+            r#"
+            [[files]]
+            path = ".config/karabiner/**"
+            when = { os = "plan9" }
+            [[files]]
+            path = ".bashrc"
+            when = { not = { os = "plan9" } }
+            "#,
+        )
+        .unwrap();
+        assert!(!config.manages(Path::new(".config/karabiner/karabiner.json")));
+        assert!(config.manages(Path::new(".bashrc")));
+        assert!(config.manages(Path::new(".zshrc")));
+    }
+
+    #[test]
+    fn relative_dest_resolves_against_config_dir() -> Result<()> {
+        let config = get_config(Path::new("test/repo/bash"))?.expect("config");
+        assert_eq!(
+            config.dest.expect("dest").canonicalize()?,
+            Path::new("test/home").canonicalize()?
+        );
+        Ok(())
+    }
+}
@@ -1,35 +1,47 @@
-use crate::backup::{backup, get_backuppath};
+use crate::backup::{BackupDir, backup};
+use crate::compare::same_content;
+use crate::config::get_config;
+use crate::hook::run_lifecycle;
 use crate::list::list_items;
+use crate::naming::apply_mode;
 use crate::structs::display_path;
-use anyhow::Result;
+use anyhow::{Context, Result};
 use colored::Colorize;
 use std::fs;
 use std::path::{Path, PathBuf};
 
-fn copy(base: &Path, backupdir: &Path) -> Result<()> {
+fn copy(base: &Path, backupdir: &BackupDir) -> Result<()> {
+    let mut changed = vec![];
     for link in list_items(base, true)? {
         fs::create_dir_all(link.target.parent().unwrap_or_else(|| Path::new("/")))?;
         if link.target.exists() {
-            let content_src = fs::read(&link.source)?;
-            if let Ok(content) = fs::read(&link.target)
-                && content == content_src
-            {
+            let same = same_content(&link.source, &link.target)
+                .with_context(|| format!("cannot compare {link}"))?;
+            if same {
                 eprintln!("{} {link} (exists)", "SKIP:".cyan());
+                if let Some(mode) = link.mode {
+                    apply_mode(&link.target, mode, false)?;
+                }
                 continue;
             }
             eprintln!("{} {}", "BACKUP:".yellow(), display_path(&link.target));
             backup(backupdir, &link.target)?;
         }
         eprintln!("{} {}", "COPY:".green(), &link);
-        fs::copy(link.source, link.target)?;
+        fs::copy(&link.source, &link.target)?;
+        if let Some(mode) = link.mode {
+            apply_mode(&link.target, mode, false)?;
+        }
+        changed.push(link.target);
     }
-    Ok(())
+    let conf = get_config(base)?;
+    run_lifecycle(conf.as_ref(), |c| c.post_copy.as_ref(), base, &changed)
 }
 
 #[test]
 fn test_copy() -> Result<()> {
     let test_base = PathBuf::from("test/repo/bash");
-    let test_backupdir = &PathBuf::from("test/backup");
+    let test_backupdir = &BackupDir::at(Path::new("test/backup"));
     copy(&test_base, test_backupdir)?;
     let copy_path = PathBuf::from("test/home/.bashrc");
     assert!(copy_path.exists());
@@ -39,7 +51,7 @@ fn test_copy() -> Result<()> {
 }
 
 pub fn copy_dirs(dirs: &[PathBuf]) -> Result<()> {
-    let backupdir = get_backuppath()?;
+    let backupdir = BackupDir::session();
     for dir in dirs {
         copy(dir, &backupdir)?
     }
@@ -0,0 +1,287 @@
+use crate::structs::sanitize_display;
+use colored::Colorize;
+use serde_json::Value;
+use std::path::Path;
+
+/// Largest binary file shown as a hex dump diff.
+pub const HEX_DIFF_LIMIT: u64 = 4096;
+const HEX_WIDTH: usize = 16;
+
+/// Lines of a hex dump: offset, bytes in hex and printable ASCII.
+pub fn hex_dump(bytes: &[u8]) -> Vec<String> {
+    bytes
+        .chunks(HEX_WIDTH)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let hex = chunk
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let ascii = chunk
+                .iter()
+                .map(|&b| match b {
+                    0x20..=0x7e => b as char,
+                    _ => '.',
+                })
+                .collect::<String>();
+            format!("{:08x}  {hex:<47}  |{ascii}|", i * HEX_WIDTH)
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl Format {
+    fn from_path(path: &Path) -> Option<Format> {
+        match path.extension()?.to_str()? {
+            "json" | "jsonc" | "json5" | "code-workspace" => Some(Format::Json),
+            "toml" => Some(Format::Toml),
+            "yaml" | "yml" => Some(Format::Yaml),
+            _ => None,
+        }
+    }
+
+    fn parse(self, text: &str) -> Option<Value> {
+        match self {
+            Format::Json => serde_json::from_str(&strip_jsonc(text)).ok(),
+            Format::Toml => toml::from_str::<toml::Value>(text).ok().map(toml_to_json),
+            Format::Yaml => serde_norway::from_str(text).ok(),
+        }
+    }
+}
+
+/// Removes the comments and trailing commas JSONC allows, as in VS Code
+/// settings, leaving strings untouched.
+fn strip_jsonc(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                out.push(c);
+                while let Some(c) = chars.next() {
+                    out.push(c);
+                    match c {
+                        '\\' => out.extend(chars.next()),
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        out.push(c);
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut last = ' ';
+                for c in chars.by_ref() {
+                    if last == '*' && c == '/' {
+                        break;
+                    }
+                    last = c;
+                }
+            }
+            ',' => {
+                let rest = chars.clone().find(|c| !c.is_whitespace());
+                if !matches!(rest, Some('}' | ']')) {
+                    out.push(c);
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn toml_to_json(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(i) => Value::from(i),
+        toml::Value::Float(f) => Value::from(f),
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Datetime(d) => Value::String(d.to_string()),
+        toml::Value::Array(a) => Value::Array(a.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(t) => {
+            Value::Object(t.into_iter().map(|(k, v)| (k, toml_to_json(v))).collect())
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Change {
+    Added(String, Value),
+    Removed(String, Value),
+    Changed(String, Value, Value),
+}
+
+fn key_path(parent: &str, key: &str) -> String {
+    let plain = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-');
+    let key = if plain {
+        key.to_owned()
+    } else {
+        Value::String(key.to_owned()).to_string()
+    };
+    match parent {
+        "" => key,
+        parent => format!("{parent}.{key}"),
+    }
+}
+
+/// Collects the key-level changes from `source` to `target`, recursing into
+/// objects regardless of key order and into arrays by index.
+fn changes(path: &str, source: &Value, target: &Value, out: &mut Vec<Change>) {
+    match (source, target) {
+        (Value::Object(s), Value::Object(t)) => {
+            for (key, sv) in s {
+                let child = key_path(path, key);
+                match t.get(key) {
+                    Some(tv) => changes(&child, sv, tv, out),
+                    None => out.push(Change::Removed(child, sv.clone())),
+                }
+            }
+            for (key, tv) in t.iter().filter(|(key, _)| !s.contains_key(*key)) {
+                out.push(Change::Added(key_path(path, key), tv.clone()));
+            }
+        }
+        (Value::Array(s), Value::Array(t)) => {
+            for i in 0..s.len().max(t.len()) {
+                let child = format!("{path}[{i}]");
+                match (s.get(i), t.get(i)) {
+                    (Some(sv), Some(tv)) => changes(&child, sv, tv, out),
+                    (Some(sv), None) => out.push(Change::Removed(child, sv.clone())),
+                    (None, Some(tv)) => out.push(Change::Added(child, tv.clone())),
+                    (None, None) => {}
+                }
+            }
+        }
+        (s, t) if s != t => out.push(Change::Changed(path.to_owned(), s.clone(), t.clone())),
+        _ => {}
+    }
+}
+
+fn show_value(value: &Value) -> String {
+    sanitize_display(&value.to_string())
+}
+
+/// Key-level diff of a JSON, TOML or YAML file chosen by the extension of
+/// `path`, or `None` for other files and when either side does not parse.
+pub fn structured_diff(path: &Path, source: &str, target: &str) -> Option<String> {
+    let format = Format::from_path(path)?;
+    let (source, target) = (format.parse(source)?, format.parse(target)?);
+    let mut found = vec![];
+    changes("", &source, &target, &mut found);
+    if found.is_empty() {
+        return Some(
+            "no changes besides formatting and key order"
+                .cyan()
+                .to_string(),
+        );
+    }
+    let lines = found
+        .iter()
+        .map(|change| match change {
+            Change::Removed(key, value) => {
+                let line = format!("- {}: {}", sanitize_display(key), show_value(value));
+                line.red().to_string()
+            }
+            Change::Added(key, value) => {
+                let line = format!("+ {}: {}", sanitize_display(key), show_value(value));
+                line.green().to_string()
+            }
+            Change::Changed(key, from, to) => {
+                let line = format!(
+                    "~ {}: {} -> {}",
+                    sanitize_display(key),
+                    show_value(from),
+                    show_value(to)
+                );
+                line.yellow().to_string()
+            }
+        })
+        .collect::<Vec<_>>();
+    Some(lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all_changes(format: Format, source: &str, target: &str) -> Vec<Change> {
+        let mut found = vec![];
+        changes(
+            "",
+            &format.parse(source).unwrap(),
+            &format.parse(target).unwrap(),
+            &mut found,
+        );
+        found
+    }
+
+    #[test]
+    fn json_diff_ignores_formatting_comments_and_key_order() {
+        let source = r#"{
+            // editor
+            "editor.fontSize": 14,
+            "files.exclude": { "**/.git": true, },
+            "url": "http://example.com/*not a comment*/",
+        }"#;
+        let target = r#"{"url": "http://example.com/*not a comment*/",
+            "files.exclude": {"**/.git": true, "**/target": true},
+            "editor.fontSize": 13}"#;
+        assert_eq!(
+            all_changes(Format::Json, source, target),
+            [
+                Change::Changed("\"editor.fontSize\"".to_owned(), 14.into(), 13.into()),
+                Change::Added("\"files.exclude\".\"**/target\"".to_owned(), true.into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn toml_and_yaml_diffs_recurse_into_tables_and_arrays() {
+        assert_eq!(
+            all_changes(
+                Format::Toml,
+                "[tool]\nname = \"a\"\nargs = [1, 2]\n",
+                "tool = { args = [1], name = \"a\" }\n"
+            ),
+            [Change::Removed("tool.args[1]".to_owned(), 2.into())]
+        );
+        assert_eq!(
+            all_changes(Format::Yaml, "a:\n  b: 1\n", "a: {b: 1}\nc: x\n"),
+            [Change::Added("c".to_owned(), "x".into())]
+        );
+    }
+
+    #[test]
+    fn selects_format_by_extension() {
+        assert_eq!(structured_diff(Path::new("notes.txt"), "{}", "{}"), None);
+        assert_eq!(structured_diff(Path::new("a.json"), "{", "{}"), None);
+        assert!(structured_diff(Path::new("a.yml"), "a: 1", "a: 2").is_some());
+    }
+
+    #[test]
+    fn hex_dump_shows_offsets_and_ascii() {
+        assert_eq!(
+            hex_dump(b"\x89PNG\r\n\x1a\n0123456789"),
+            [
+                "00000000  89 50 4e 47 0d 0a 1a 0a 30 31 32 33 34 35 36 37  |.PNG....01234567|",
+                "00000010  38 39                                            |89|",
+            ]
+        );
+    }
+}
@@ -0,0 +1,202 @@
+use crate::{
+    config::{Config, IgnoreSource, find_config},
+    dest::get_dest,
+    filter::{Filter, git_global, matched},
+    list::{list_diritems, list_items},
+    naming,
+    show::classify,
+    structs::{display_path, sanitize_display},
+};
+use anyhow::{Result, bail};
+use colored::Colorize;
+use glob::Pattern;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// `path` with its parent directory canonicalized, so that symlinks, broken
+/// ones included, are named rather than followed.
+fn normalize(path: &Path) -> PathBuf {
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    match (
+        path.parent().and_then(|p| p.canonicalize().ok()),
+        path.file_name(),
+    ) {
+        (Some(parent), Some(name)) => parent.join(name),
+        _ => path,
+    }
+}
+
+fn show_configs(dir: &Path) -> Result<Option<Config>> {
+    let conf = find_config(dir, &mut |path, reason| {
+        eprintln!("{}: {} ({reason})", "SKIPPED".yellow(), display_path(path));
+    })?;
+    match &conf {
+        Some(conf) => eprintln!("{}: {}", "CONFIG".cyan(), display_path(&conf.path)),
+        None => eprintln!("{}: none, using defaults", "CONFIG".cyan()),
+    }
+    Ok(conf)
+}
+
+/// The rule hiding `path` or one of its parents below `dir` from the walk.
+fn ignore_rule(dir: &Path, path: &Path, conf: Option<&Config>) -> Result<Option<String>> {
+    let filter = Filter::new(dir, conf)?;
+    // As for the walk, the ignore files of git only count inside a git
+    // repository, where `.gitignore` files above `dir` apply too.
+    let git_root = dir.ancestors().find(|d| d.join(".git").exists());
+    let honors = |source| conf.is_none_or(|c| c.honors(source));
+    let git = git_root.filter(|_| honors(IgnoreSource::Git));
+    let global_git = git_root
+        .filter(|_| honors(IgnoreSource::GlobalGit))
+        .map(git_global);
+    let rel = path.strip_prefix(dir).unwrap_or(path);
+    let mut current = dir.to_path_buf();
+    for component in rel.components() {
+        current.push(component);
+        let is_dir = current != path || path.is_dir();
+        let ignore = honors(IgnoreSource::Ignore)
+            .then(|| filter.nearest_within(&current, is_dir, ".ignore", Path::new("/")))
+            .flatten();
+        let ignored = ignore
+            .or_else(|| {
+                let root = git?;
+                filter
+                    .nearest_within(&current, is_dir, ".gitignore", root)
+                    .or_else(|| {
+                        filter.in_file(root, &root.join(".git/info/exclude"), &current, is_dir)
+                    })
+            })
+            .or_else(|| matched(global_git.as_ref()?, &current, is_dir))
+            .filter(|(keep, _)| !keep);
+        let decision = ignored.or_else(|| filter.decide(&current, is_dir));
+        if let Some((false, rule)) = decision {
+            if current != path {
+                return Ok(Some(format!("{rule}, matching {}", display_path(&current))));
+            }
+            return Ok(Some(rule));
+        }
+    }
+    Ok(None)
+}
+
+fn explain_source(dir: &Path, path: &Path) -> Result<()> {
+    let conf = show_configs(dir)?;
+    let rel = path.strip_prefix(dir).unwrap_or(path);
+    if rel.as_os_str().is_empty() {
+        bail!("{} is the processed directory", display_path(path));
+    }
+    eprintln!("{}: {}", "DEST".cyan(), display_path(&get_dest(path)?));
+    if let Some(rule) = ignore_rule(dir, path, conf.as_ref())? {
+        eprintln!("{}: by {rule}", "IGNORED".yellow());
+        return Ok(());
+    }
+    if let Some(rule) = conf
+        .iter()
+        .flat_map(|c| c.files.iter().flatten())
+        .find(|rule| {
+            Pattern::new(&rule.path).is_ok_and(|p| p.matches_path(rel)) && !rule.when.matches()
+        })
+    {
+        eprintln!(
+            "{}: `[[files]]` rule `{}` does not match this system",
+            "SKIPPED".yellow(),
+            sanitize_display(&rule.path)
+        );
+        return Ok(());
+    }
+    let mut source = path.to_path_buf();
+    if let Some(item) = list_diritems(dir)?
+        .into_iter()
+        .filter_map(|item| item.canonicalize().ok())
+        .find(|item| path.starts_with(item))
+    {
+        eprintln!("{}: {}", "DIRS".cyan(), display_path(&item));
+        source = item;
+    }
+    let link = list_items(dir, false)?
+        .into_iter()
+        .find(|link| link.source == source);
+    match link {
+        Some(link) => {
+            eprintln!("{}: {}", "TARGET".cyan(), display_path(&link.target));
+            eprintln!("{}: {}", "STATUS".cyan(), classify(link).status.label());
+        }
+        None if path.is_dir() => eprintln!(
+            "{}: directories are only linked when listed in `dirs`, their files are linked instead",
+            "NOT LINKED".yellow()
+        ),
+        None if !path.exists() && path.symlink_metadata().is_ok() => {
+            eprintln!("{}: broken symlink in the repo", "NOT LINKED".yellow())
+        }
+        None if !path.exists() => bail!("{} does not exist", display_path(path)),
+        None => eprintln!("{}: hidden from the walk", "NOT LINKED".yellow()),
+    }
+    Ok(())
+}
+
+/// The repo path linked to `target`, the one that would be, or `None`.
+fn find_source(dir: &Path, target: &Path) -> Result<Option<PathBuf>> {
+    let linked = list_items(dir, false)?
+        .into_iter()
+        .find(|link| link.target == target || target.starts_with(&link.target) && link.is_dir);
+    if let Some(link) = linked {
+        return Ok(Some(match target.strip_prefix(&link.target) {
+            Ok(rest) if !rest.as_os_str().is_empty() => link.source.join(rest),
+            _ => link.source,
+        }));
+    }
+    let Some(conf) = find_config(dir, &mut |_, _| {})? else {
+        return Ok(None);
+    };
+    let dest = conf.dest.clone().or_else(dirs::home_dir);
+    let Some(rel) = dest
+        .and_then(|d| d.canonicalize().ok())
+        .and_then(|d| target.strip_prefix(d).ok().map(Path::to_path_buf))
+    else {
+        return Ok(None);
+    };
+    if !conf.dotted_names.unwrap_or_default() {
+        return Ok(Some(dir.join(rel)));
+    }
+    // Only the last component can carry mode prefixes, so look for the entry
+    // decoding to `rel` next to the one without them.
+    let encoded = dir.join(naming::encode(&rel, None));
+    let found = encoded
+        .parent()
+        .and_then(|parent| fs::read_dir(parent).ok())
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .find(|path| {
+            let decoded = path.strip_prefix(dir).ok().map(naming::decode);
+            matches!(decoded, Some(Ok((decoded, _))) if decoded == rel)
+        });
+    Ok(Some(found.unwrap_or(encoded)))
+}
+
+/// Explains whether `path`, in the repo or at the destination, is managed
+/// by one of `dirs`, and why.
+pub fn explain(dirs: &[PathBuf], path: &Path) -> Result<()> {
+    let path = normalize(path);
+    let dirs = dirs
+        .iter()
+        .map(|dir| dir.canonicalize())
+        .collect::<Result<Vec<_>, _>>()?;
+    if let Some(dir) = dirs.iter().find(|dir| path.starts_with(dir)) {
+        return explain_source(dir, &path);
+    }
+    for dir in &dirs {
+        if let Some(source) = find_source(dir, &path)? {
+            eprintln!("{}: {}", "SOURCE".cyan(), display_path(&source));
+            return explain_source(dir, &source);
+        }
+    }
+    bail!(
+        "{} is neither in {} nor below their destinations",
+        display_path(&path),
+        dirs.iter()
+            .map(|dir| display_path(dir))
+            .collect::<Vec<_>>()
+            .join(", ")
+    )
+}
@@ -0,0 +1,234 @@
+use crate::{
+    CONFFILE_NAME, IGNOREFILE_NAME,
+    config::{Config, GlobRule, GlobalConfig, IgnoreSource},
+    structs::{display_path, sanitize_display},
+};
+use anyhow::{Context, Result};
+use glob::Pattern;
+use ignore::{
+    Match,
+    gitignore::{Gitignore, GitignoreBuilder, Glob},
+};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Names never managed unless included again.
+const IGNORED_NAMES: [&str; 5] = [
+    CONFFILE_NAME,
+    IGNOREFILE_NAME,
+    ".git",
+    ".gitignore",
+    ".gitmodules",
+];
+
+/// Decides which paths below a directory are managed. The first of these
+/// that matches wins:
+///
+/// 1. `include` globs of the config,
+/// 2. the nearest `.wagonignore`, including its `!` negations,
+/// 3. `ignore` globs of the config,
+/// 4. the global ignore file, including its `!` negations,
+/// 5. the names in [`IGNORED_NAMES`].
+pub struct Filter {
+    base: PathBuf,
+    include: Vec<Pattern>,
+    ignore: Vec<Pattern>,
+    global: Gitignore,
+    /// Whether `.wagonignore` files are honored.
+    wagonignore: bool,
+    /// Parsed ignore files seen so far.
+    ignore_files: Mutex<HashMap<PathBuf, Option<Gitignore>>>,
+}
+
+fn patterns(rules: Option<&Vec<GlobRule>>, field: &str) -> Result<Vec<Pattern>> {
+    rules
+        .into_iter()
+        .flatten()
+        .filter_map(GlobRule::active)
+        .map(|glob| {
+            Pattern::new(glob)
+                .with_context(|| format!("invalid `{field}` glob `{}`", sanitize_display(glob)))
+        })
+        .collect()
+}
+
+fn gitignore(root: &Path, file: &Path) -> Option<Gitignore> {
+    if !file.is_file() {
+        return None;
+    }
+    let mut builder = GitignoreBuilder::new(root);
+    if let Some(err) = builder.add(file) {
+        tracing::warn!("{err}");
+    }
+    builder.build().ok()
+}
+
+impl Filter {
+    pub fn new(base: &Path, conf: Option<&Config>) -> Result<Filter> {
+        Ok(Filter {
+            base: base.to_path_buf(),
+            include: patterns(conf.and_then(|c| c.include.as_ref()), "include")?,
+            ignore: patterns(conf.and_then(|c| c.ignore.as_ref()), "ignore")?,
+            global: gitignore(base, &GlobalConfig::ignore_path()).unwrap_or_else(Gitignore::empty),
+            wagonignore: conf.is_none_or(|c| c.honors(IgnoreSource::Wagonignore)),
+            ignore_files: Mutex::default(),
+        })
+    }
+
+    /// Match of the ignore file `name` nearest to `path`, looking up to the
+    /// base, as whether the path is kept and the matching line.
+    pub fn nearest(&self, path: &Path, is_dir: bool, name: &str) -> Option<(bool, String)> {
+        self.nearest_within(path, is_dir, name, &self.base)
+    }
+
+    /// Like [`Filter::nearest`], looking up to `top` instead of the base.
+    pub fn nearest_within(
+        &self,
+        path: &Path,
+        is_dir: bool,
+        name: &str,
+        top: &Path,
+    ) -> Option<(bool, String)> {
+        for dir in path.ancestors().skip(1) {
+            if let Some(decision) = self.in_file(dir, &dir.join(name), path, is_dir) {
+                return Some(decision);
+            }
+            if dir == top {
+                break;
+            }
+        }
+        None
+    }
+
+    /// Match of the ignore file `file`, rooted at `root`, for `path`.
+    pub fn in_file(
+        &self,
+        root: &Path,
+        file: &Path,
+        path: &Path,
+        is_dir: bool,
+    ) -> Option<(bool, String)> {
+        let mut cache = self.ignore_files.lock().unwrap_or_else(|e| e.into_inner());
+        cache
+            .entry(file.to_path_buf())
+            .or_insert_with(|| gitignore(root, file))
+            .as_ref()
+            .and_then(|gi| decide(gi.matched(path, is_dir)))
+    }
+
+    /// Whether `path`, below the base, is managed, with the rule deciding it
+    /// or `None` when no rule matches and the path is managed.
+    pub fn decide(&self, path: &Path, is_dir: bool) -> Option<(bool, String)> {
+        let rel = path.strip_prefix(&self.base).unwrap_or(path);
+        if let Some(p) = self.include.iter().find(|p| p.matches_path(rel)) {
+            return Some((
+                true,
+                format!("`include` glob `{}`", sanitize_display(p.as_str())),
+            ));
+        }
+        if self.wagonignore
+            && let Some(decision) = self.nearest(path, is_dir, IGNOREFILE_NAME)
+        {
+            return Some(decision);
+        }
+        if let Some(p) = self.ignore.iter().find(|p| p.matches_path(rel)) {
+            return Some((
+                false,
+                format!("`ignore` glob `{}`", sanitize_display(p.as_str())),
+            ));
+        }
+        if let Some(decision) = decide(self.global.matched(path, is_dir)) {
+            return Some(decision);
+        }
+        let name = rel.file_name().unwrap_or_default();
+        IGNORED_NAMES
+            .iter()
+            .find(|ignored| name == **ignored)
+            .map(|ignored| (false, format!("built-in name `{ignored}`")))
+    }
+
+    pub fn keeps(&self, path: &Path, is_dir: bool) -> bool {
+        self.decide(path, is_dir).is_none_or(|(keep, _)| keep)
+    }
+}
+
+/// The global excludes file of git, for the repository at `root`.
+pub fn git_global(root: &Path) -> Gitignore {
+    let (global, err) = GitignoreBuilder::new(root).build_global();
+    if let Some(err) = err {
+        tracing::warn!("{err}");
+    }
+    global
+}
+
+/// Match of `gitignore` for `path`, as whether the path is kept and the
+/// matching line.
+pub fn matched(gitignore: &Gitignore, path: &Path, is_dir: bool) -> Option<(bool, String)> {
+    decide(gitignore.matched(path, is_dir))
+}
+
+/// Describes a gitignore match as whether the path is kept and the line of
+/// the file that matched.
+fn decide(matched: Match<&Glob>) -> Option<(bool, String)> {
+    let (keep, glob) = match matched {
+        Match::None => return None,
+        Match::Ignore(glob) => (false, glob),
+        Match::Whitelist(glob) => (true, glob),
+    };
+    let line = if keep {
+        format!("!{}", glob.original())
+    } else {
+        glob.original().to_owned()
+    };
+    let from = glob.from().map(display_path).unwrap_or_default();
+    Some((keep, format!("`{}` in {from}", sanitize_display(&line))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn config(text: &str) -> Config {
+        toml::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn include_and_negation_override_ignored_names() {
+        let base = std::env::temp_dir().join(format!("wagon-filter-{}", std::process::id()));
+        fs::create_dir_all(base.join("sub")).unwrap();
+        fs::write(base.join("sub/.wagonignore"), "!.gitmodules\nnotes.md\n").unwrap();
+        let conf = config(
+            r#"
+            ignore = ["*.bak", { path = "work/*", when = { env = ["WAGON_TEST_UNSET_PROFILE"] } }]
+            include = ["config/git/.gitignore", "keep.bak"]
+            "#,
+        );
+        let filter = Filter::new(&base, Some(&conf)).unwrap();
+        let keeps = |path: &str| filter.keeps(&base.join(path), false);
+        let results = [
+            keeps(".gitignore"),
+            keeps("config/git/.gitignore"),
+            keeps("sub/.gitmodules"),
+            keeps(".gitmodules"),
+            keeps("sub/notes.md"),
+            keeps("old.bak"),
+            keeps("keep.bak"),
+            keeps("work/vimrc"),
+            keeps(".vimrc"),
+        ];
+        let _ = fs::remove_dir_all(&base);
+        assert_eq!(
+            results,
+            [false, true, true, false, false, false, true, true, true]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_globs() {
+        let conf = config("ignore = [\"[\"]\n");
+        let err = Filter::new(Path::new("."), Some(&conf)).err().unwrap();
+        assert!(err.to_string().contains("invalid `ignore` glob"));
+    }
+}
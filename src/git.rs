@@ -0,0 +1,56 @@
+use anyhow::{Result, bail};
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+fn git(repo: &Path, args: &[&str]) -> Result<Output> {
+    Ok(Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()?)
+}
+
+/// Runs git in `repo` and returns its trimmed stdout, failing with its stderr.
+pub fn run(repo: &Path, args: &[&str]) -> Result<String> {
+    let output = git(repo, args)?;
+    if !output.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+pub fn is_dirty(repo: &Path) -> Result<bool> {
+    Ok(!run(repo, &["status", "--porcelain"])?.is_empty())
+}
+
+pub fn is_bare(repo: &Path) -> Result<bool> {
+    Ok(run(repo, &["rev-parse", "--is-bare-repository"])? == "true")
+}
+
+pub fn branch(repo: &Path) -> Result<String> {
+    run(repo, &["rev-parse", "--abbrev-ref", "HEAD"])
+}
+
+/// Commits ahead of and behind the upstream, `None` without an upstream.
+pub fn ahead_behind(repo: &Path) -> Result<Option<(usize, usize)>> {
+    if !git(repo, &["rev-parse", "--abbrev-ref", "@{u}"])?
+        .status
+        .success()
+    {
+        return Ok(None);
+    }
+    let counts = run(
+        repo,
+        &["rev-list", "--left-right", "--count", "HEAD...@{u}"],
+    )?;
+    let mut counts = counts.split_whitespace().map(str::parse::<usize>);
+    match (counts.next(), counts.next()) {
+        (Some(Ok(ahead)), Some(Ok(behind))) => Ok(Some((ahead, behind))),
+        _ => bail!("unexpected output of git rev-list"),
+    }
+}
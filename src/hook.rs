@@ -0,0 +1,623 @@
+use crate::{
+    config::{Command, Config, expand_path},
+    record::{Record, hash_files},
+    structs::{display_path, sanitize_display, sanitize_output},
+};
+use anyhow::{Context, Result, bail};
+use colored::Colorize;
+use std::env::consts;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{self, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const SHELL: &str = "sh";
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Builds the process for a hook: `sh -c` when `shell` is set, the config's
+/// directory as default working directory, and `WAGON_*` variables describing
+/// the run exported next to the hook's own `env`.
+pub fn build_command(conf: &Config, hook: &Command, base: &Path) -> Result<process::Command> {
+    let mut command = if hook.shell.unwrap_or_default() {
+        let mut command = process::Command::new(SHELL);
+        command.arg("-c").arg(&hook.command).arg(SHELL);
+        command
+    } else {
+        process::Command::new(&hook.command)
+    };
+    command.args(&hook.args);
+    prepare(&mut command, conf, hook, base)?;
+    Ok(command)
+}
+
+fn prepare(
+    command: &mut process::Command,
+    conf: &Config,
+    hook: &Command,
+    base: &Path,
+) -> Result<()> {
+    let cwd = match &hook.cwd {
+        Some(cwd) => expand_path(cwd, conf.dir()).context("cannot expand `cwd` of hook")?,
+        None => conf.dir().to_path_buf(),
+    };
+    if !cwd.as_os_str().is_empty() {
+        command.current_dir(cwd);
+    }
+    let dest = match &conf.dest {
+        Some(dest) => dest.clone(),
+        None => dirs::home_dir().context("cant get home dir")?,
+    };
+    command
+        .env(
+            "WAGON_BASE",
+            base.canonicalize().unwrap_or(base.to_path_buf()),
+        )
+        .env("WAGON_CONFIG", &conf.path)
+        .env("WAGON_DEST", dest)
+        .env("WAGON_OS", consts::OS)
+        .env("WAGON_ARCH", consts::ARCH)
+        .envs(hook.env.iter().flatten());
+    Ok(())
+}
+
+fn describe(hook: &Command) -> String {
+    let mut line = sanitize_display(&hook.command);
+    if !hook.args.is_empty() {
+        line.push(' ');
+        line.push_str(&sanitize_display(&hook.args.join(" ")));
+    }
+    line
+}
+
+fn stream_lines(reader: impl Read + Send + 'static, prefix: String) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut reader = BufReader::new(reader);
+        let mut buf = Vec::new();
+        while let Ok(n) = reader.read_until(b'\n', &mut buf) {
+            if n == 0 {
+                break;
+            }
+            let line = String::from_utf8_lossy(&buf);
+            eprintln!("{prefix}{}", sanitize_output(line.trim_end_matches('\n')));
+            buf.clear();
+        }
+    })
+}
+
+#[derive(Debug)]
+struct TimedOut(Duration);
+
+impl std::fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "timed out after {}s", self.0.as_secs_f64())
+    }
+}
+
+impl std::error::Error for TimedOut {}
+
+fn wait(child: &mut process::Child, timeout: Option<Duration>) -> Result<ExitStatus> {
+    // A deadline too far away to represent is no deadline.
+    let Some((timeout, deadline)) = timeout.and_then(|t| Some((t, Instant::now().checked_add(t)?)))
+    else {
+        return Ok(child.wait()?);
+    };
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        if Instant::now() >= deadline {
+            unsafe { libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL) };
+            child.wait()?;
+            return Err(TimedOut(timeout).into());
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn run_hook(
+    conf: &Config,
+    hook: &Command,
+    base: &Path,
+    changed: Option<&str>,
+    prefix: &str,
+) -> Result<()> {
+    let mut command = build_command(conf, hook, base)?;
+    if let Some(changed) = changed {
+        command.env("WAGON_CHANGED", changed).stdin(Stdio::piped());
+    } else {
+        command.stdin(Stdio::null());
+    }
+    if hook.timeout.is_some() {
+        // Own process group, so that a timeout also kills the hook's children.
+        command.process_group(0);
+    }
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    if let (Some(mut stdin), Some(changed)) = (child.stdin.take(), changed) {
+        let input = format!("{changed}\n");
+        // Hooks are free to ignore their input, so a closed pipe is fine.
+        thread::spawn(move || stdin.write_all(input.as_bytes()));
+    }
+    let streams = [
+        child
+            .stdout
+            .take()
+            .map(|r| stream_lines(r, prefix.to_owned())),
+        child
+            .stderr
+            .take()
+            .map(|r| stream_lines(r, prefix.to_owned())),
+    ];
+    let status = wait(&mut child, hook.timeout);
+    for stream in streams.into_iter().flatten() {
+        let _ = stream.join();
+    }
+    let status = status?;
+    if !status.success() {
+        bail!("{status}");
+    }
+    Ok(())
+}
+
+fn quote(word: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "_-./:=@%+,".contains(c);
+    if !word.is_empty() && word.chars().all(safe) {
+        word.to_owned()
+    } else {
+        format!("'{}'", word.replace('\'', "'\\''"))
+    }
+}
+
+fn quote_all<'a>(words: impl IntoIterator<Item = &'a std::ffi::OsStr>) -> String {
+    words
+        .into_iter()
+        .map(|w| quote(&w.to_string_lossy()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Renders a hook, with its guards, as a line of a POSIX shell script.
+pub fn render(conf: &Config, hook: &Command, base: &Path) -> Result<String> {
+    let command = build_command(conf, hook, base)?;
+    let mut line = String::from("(");
+    if let Some(cwd) = command.get_current_dir() {
+        line.push_str(&format!("cd {} || exit 1; ", quote_all([cwd.as_os_str()])));
+    }
+    if let Some(creates) = &hook.creates {
+        let path = expand_path(creates, conf.dir())?;
+        line.push_str(&format!("[ -e {} ] || ", quote_all([path.as_os_str()])));
+    }
+    if let Some(unless) = &hook.unless {
+        let unless = quote_all(unless.iter().map(|s| s.as_ref()));
+        line.push_str(&format!("{unless} >/dev/null 2>&1 || "));
+    }
+    line.push_str("env");
+    for (key, value) in command.get_envs() {
+        if let Some(value) = value {
+            let mut pair = key.to_os_string();
+            pair.push("=");
+            pair.push(value);
+            line.push(' ');
+            line.push_str(&quote_all([pair.as_os_str()]));
+        }
+    }
+    line.push(' ');
+    line.push_str(&quote_all(
+        std::iter::once(command.get_program()).chain(command.get_args()),
+    ));
+    line.push(')');
+    if hook.continue_on_error.unwrap_or_default() {
+        line.push_str(" || true");
+    }
+    Ok(line)
+}
+
+/// Returns why the hook can be skipped, checking `creates` and `unless`.
+fn guard(conf: &Config, hook: &Command, base: &Path) -> Result<Option<String>> {
+    if let Some(creates) = &hook.creates {
+        let path = expand_path(creates, conf.dir()).context("cannot expand `creates` of hook")?;
+        if path.exists() {
+            return Ok(Some(format!("{} exists", display_path(&path))));
+        }
+    }
+    if let Some((program, args)) = hook.unless.as_deref().and_then(|u| u.split_first()) {
+        let mut command = process::Command::new(program);
+        command.args(args);
+        prepare(&mut command, conf, hook, base)?;
+        if hook.timeout.is_some() {
+            command.process_group(0);
+        }
+        let child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        // A probe that cannot start counts as failed, one that hangs is
+        // killed like the hook.
+        let succeeded = match child {
+            Ok(mut child) => wait(&mut child, hook.timeout)
+                .context("`unless` probe")?
+                .success(),
+            Err(_) => false,
+        };
+        if succeeded {
+            return Ok(Some(format!("`{}` succeeded", sanitize_display(program))));
+        }
+    }
+    Ok(None)
+}
+
+fn record_key(conf: &Config, hook: &Command) -> String {
+    let path = conf.path.canonicalize().unwrap_or(conf.path.clone());
+    let mut argv = vec![hook.command.as_str()];
+    argv.extend(hook.args.iter().map(String::as_str));
+    format!("{}: {}", path.display(), argv.join(" "))
+}
+
+struct Failure {
+    hook: String,
+    config: String,
+    reason: String,
+    ignored: bool,
+}
+
+struct Run {
+    hook: String,
+    dir: String,
+    status: &'static str,
+    duration: Duration,
+}
+
+/// Collects hook failures over a run of `init` or `update`.
+#[derive(Default)]
+pub struct Report {
+    failures: Vec<Failure>,
+    runs: Vec<Run>,
+    record: Option<(PathBuf, Record)>,
+    force: bool,
+    changed: Option<String>,
+    prefix: String,
+}
+
+impl Report {
+    /// Skips hooks that already succeeded according to the record at `path`,
+    /// unless `force` is set, and records hooks as they succeed.
+    pub fn with_record(path: PathBuf, force: bool) -> Result<Self> {
+        let record = Record::load(&path)?;
+        Ok(Self {
+            record: Some((path, record)),
+            force,
+            ..Self::default()
+        })
+    }
+
+    /// Passes `targets` to the hooks, one per line, through `WAGON_CHANGED`
+    /// and stdin.
+    pub fn with_changed(mut self, targets: &[PathBuf]) -> Self {
+        let lines = targets
+            .iter()
+            .map(|t| t.to_string_lossy())
+            .collect::<Vec<_>>();
+        self.changed = Some(lines.join("\n"));
+        self
+    }
+
+    /// Prefixes the output of the hooks with `[name]`.
+    pub fn with_prefix(mut self, name: &str) -> Self {
+        self.prefix = format!("[{}] ", sanitize_display(name));
+        self
+    }
+
+    /// Takes over the failures and runs collected by `other`.
+    pub fn merge(&mut self, other: Report) {
+        self.failures.extend(other.failures);
+        self.runs.extend(other.runs);
+    }
+
+    fn recorded(&self, key: &str, hash: Option<&str>) -> bool {
+        !self.force
+            && self
+                .record
+                .as_ref()
+                .is_some_and(|(_, record)| record.is_done(key, hash))
+    }
+
+    fn remember(&mut self, key: String, hash: Option<String>) -> Result<()> {
+        if let Some((path, record)) = &mut self.record {
+            record.insert(key, hash)?;
+            record.save(path)?;
+        }
+        Ok(())
+    }
+
+    /// Runs `hooks` for the current OS in order. Returns `false` when a hook
+    /// without `continue_on_error` failed and the run has to stop.
+    pub fn run_hooks(&mut self, conf: &Config, hooks: &[Command], base: &Path) -> Result<bool> {
+        for hook in hooks.iter().filter(|hook| hook.applies()) {
+            let key = record_key(conf, hook);
+            let hash = match &hook.onchange {
+                Some(patterns) => Some(hash_files(conf.dir(), patterns)?),
+                None => None,
+            };
+            let prefix = &self.prefix;
+            let started = Instant::now();
+            let skipped = if self.recorded(&key, hash.as_deref()) {
+                Ok(Some("already done".to_owned()))
+            } else {
+                guard(conf, hook, base)
+            };
+            let result = match skipped {
+                Ok(Some(reason)) => {
+                    eprintln!(
+                        "{prefix}{}: {} ({reason})",
+                        "SKIPPED".cyan(),
+                        describe(hook)
+                    );
+                    self.push_run(conf, hook, "SKIPPED", Duration::ZERO);
+                    continue;
+                }
+                Ok(None) => {
+                    eprintln!("{prefix}{}: {}", "COMMAND".cyan(), describe(hook));
+                    run_hook(conf, hook, base, self.changed.as_deref(), prefix)
+                }
+                // A hanging `unless` probe fails the hook as a timeout.
+                Err(err) if err.is::<TimedOut>() => Err(err),
+                Err(err) => return Err(err),
+            };
+            let status = match &result {
+                Ok(()) => "OK",
+                Err(err) if err.is::<TimedOut>() => "TIMEOUT",
+                Err(_) => "FAILED",
+            };
+            self.push_run(conf, hook, status, started.elapsed());
+            match result {
+                Ok(()) => self.remember(key, hash)?,
+                Err(err) => {
+                    let ignored = hook.continue_on_error.unwrap_or_default();
+                    eprintln!(
+                        "{}{}: {} ({err:#})",
+                        self.prefix,
+                        "FAILED".red(),
+                        describe(hook)
+                    );
+                    self.failures.push(Failure {
+                        hook: describe(hook),
+                        config: display_path(&conf.path),
+                        reason: format!("{err:#}"),
+                        ignored,
+                    });
+                    if !ignored {
+                        return Ok(false);
+                    }
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    fn push_run(
+        &mut self,
+        conf: &Config,
+        hook: &Command,
+        status: &'static str,
+        duration: Duration,
+    ) {
+        self.runs.push(Run {
+            hook: describe(hook),
+            dir: display_path(conf.dir()),
+            status,
+            duration,
+        });
+    }
+
+    /// Prints a table of the hooks that ran with their statuses and durations.
+    pub fn print_table(&self) {
+        if self.runs.is_empty() {
+            return;
+        }
+        let width = |f: fn(&Run) -> usize, title: &str| {
+            self.runs.iter().map(f).max().unwrap_or(0).max(title.len())
+        };
+        let status_width = width(|r| r.status.len(), "STATUS");
+        let dir_width = width(|r| r.dir.chars().count(), "DIR");
+        eprintln!(
+            "{}",
+            format!(
+                "{:status_width$}  {:>8}  {:dir_width$}  HOOK",
+                "STATUS", "TIME", "DIR"
+            )
+            .bold()
+        );
+        for run in &self.runs {
+            let status = format!("{:status_width$}", run.status);
+            let status = match run.status {
+                "OK" => status.green(),
+                "SKIPPED" => status.cyan(),
+                _ => status.red(),
+            };
+            eprintln!(
+                "{status}  {:>7.2}s  {:dir_width$}  {}",
+                run.duration.as_secs_f64(),
+                run.dir,
+                run.hook
+            );
+        }
+    }
+
+    /// Prints a summary of failed hooks and fails unless all of them were
+    /// allowed to fail.
+    pub fn finish(self) -> Result<()> {
+        if self.failures.is_empty() {
+            return Ok(());
+        }
+        eprintln!("{}", "Failed hooks:".bold());
+        for failure in &self.failures {
+            let label = if failure.ignored {
+                "IGNORED".yellow()
+            } else {
+                "FAILED".red()
+            };
+            eprintln!(
+                "{label}: {} in {} ({})",
+                failure.hook, failure.config, failure.reason
+            );
+        }
+        let fatal = self.failures.iter().filter(|f| !f.ignored).count();
+        if fatal > 0 {
+            bail!("{fatal} hook(s) failed");
+        }
+        Ok(())
+    }
+}
+
+/// Runs lifecycle hooks around link, unlink and copy when `targets` is not
+/// empty, failing when any of them fails.
+pub fn run_lifecycle(
+    conf: Option<&Config>,
+    hooks: fn(&Config) -> Option<&Vec<Command>>,
+    base: &Path,
+    targets: &[PathBuf],
+) -> Result<()> {
+    let Some((conf, hooks)) = conf.and_then(|conf| Some((conf, hooks(conf)?))) else {
+        return Ok(());
+    };
+    if targets.is_empty() {
+        return Ok(());
+    }
+    let mut report = Report::default().with_changed(targets);
+    report.run_hooks(conf, hooks, base)?;
+    report.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::OsStr;
+    use std::path::PathBuf;
+
+    fn parse(conf: &str) -> Config {
+        let mut config = toml::from_str::<Config>(conf).unwrap();
+        config.path = PathBuf::from("/repo/tmux/.wagon.toml");
+        config
+    }
+
+    fn env<'a>(command: &'a process::Command, key: &str) -> Option<&'a OsStr> {
+        command
+            .get_envs()
+            .find(|(k, _)| *k == key)
+            .and_then(|(_, v)| v)
+    }
+
+    #[test]
+    fn shell_hook_runs_through_sh_in_config_dir() -> Result<()> {
+        let conf = parse(
+            r#"
+            dest = "/home/user"
+            [[init]]
+            command = "tmux source-file $WAGON_DEST/.tmux.conf"
+            shell = true
+            env = { TMUX_TMPDIR = "/tmp" }
+            "#,
+        );
+        let hook = &conf.init.as_ref().unwrap()[0];
+        let command = build_command(&conf, hook, Path::new("/repo/tmux"))?;
+        assert_eq!(command.get_program(), SHELL);
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            ["-c", "tmux source-file $WAGON_DEST/.tmux.conf", SHELL]
+        );
+        assert_eq!(command.get_current_dir(), Some(Path::new("/repo/tmux")));
+        assert_eq!(env(&command, "WAGON_DEST"), Some(OsStr::new("/home/user")));
+        assert_eq!(env(&command, "WAGON_OS"), Some(OsStr::new(consts::OS)));
+        assert_eq!(env(&command, "TMUX_TMPDIR"), Some(OsStr::new("/tmp")));
+        Ok(())
+    }
+
+    fn parse_in_temp_dir(conf: &str) -> Config {
+        let mut config = toml::from_str::<Config>(conf).unwrap();
+        config.path = std::env::temp_dir().join(".wagon.toml");
+        config
+    }
+
+    #[test]
+    fn failing_hook_stops_the_run() -> Result<()> {
+        let conf = parse_in_temp_dir(
+            r#"
+            [[init]]
+            command = "exit 3"
+            shell = true
+            [[init]]
+            command = "true"
+            "#,
+        );
+        let mut report = Report::default();
+        let hooks = conf.init.as_deref().unwrap();
+        assert!(!report.run_hooks(&conf, hooks, Path::new("."))?);
+        assert_eq!(report.failures.len(), 1);
+        assert_eq!(report.failures[0].reason, "exit status: 3");
+        assert!(report.finish().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn continue_on_error_keeps_going() -> Result<()> {
+        let conf = parse_in_temp_dir(
+            r#"
+            [[init]]
+            command = "wagon-missing-command"
+            continue_on_error = true
+            [[init]]
+            command = "true"
+            "#,
+        );
+        let mut report = Report::default();
+        let hooks = conf.init.as_deref().unwrap();
+        assert!(report.run_hooks(&conf, hooks, Path::new("."))?);
+        assert_eq!(report.failures.len(), 1);
+        assert!(report.failures[0].ignored);
+        report.finish()
+    }
+
+    #[test]
+    fn renders_hooks_as_shell() -> Result<()> {
+        let conf = parse(
+            r#"
+            dest = "/home/user"
+            [[init]]
+            command = "echo 'hi'"
+            shell = true
+            unless = ["test", "-f", "done"]
+            continue_on_error = true
+            "#,
+        );
+        let hook = &conf.init.as_ref().unwrap()[0];
+        let line = render(&conf, hook, Path::new("/repo/tmux"))?;
+        assert!(line.starts_with("(cd /repo/tmux || exit 1; test -f done >/dev/null 2>&1 || env "));
+        assert!(line.contains(" WAGON_DEST=/home/user "));
+        assert!(line.ends_with(" sh -c 'echo '\\''hi'\\''' sh) || true"));
+        Ok(())
+    }
+
+    #[test]
+    fn hook_cwd_is_relative_to_config_dir() -> Result<()> {
+        let conf = parse(
+            r#"
+            [[update]]
+            command = "make"
+            cwd = "build"
+            "#,
+        );
+        let hook = &conf.update.as_ref().unwrap()[0];
+        let command = build_command(&conf, hook, Path::new("/repo/tmux"))?;
+        assert_eq!(command.get_program(), "make");
+        assert_eq!(
+            command.get_current_dir(),
+            Some(Path::new("/repo/tmux/build"))
+        );
+        Ok(())
+    }
+}
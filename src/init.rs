@@ -1,33 +1,35 @@
 use crate::{
-    config::get_config,
-    structs::{sanitize_display, sanitize_output},
+    config::{Config, state_dir},
+    hook::{Report, render},
+    order::{ordered_configs, ordered_hooks},
+    packages::package_hooks,
 };
 use anyhow::Result;
-use colored::Colorize;
-use std::env::consts;
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
+use std::slice;
 
-fn run_init(base: &Path) -> Result<()> {
-    if let Some(conf) = get_config(base)? {
-        for initc in conf.init.unwrap_or_default() {
-            if let Some(os) = initc.os
-                && !os.starts_with(consts::OS)
-            {
-                continue;
-            }
-            eprintln!(
-                "{}: {} {}",
-                "COMMAND".cyan(),
-                sanitize_display(&initc.command),
-                sanitize_display(&initc.args.join(" "))
-            );
-            match std::process::Command::new(initc.command)
-                .args(initc.args)
-                .output()
-            {
-                Ok(out) => eprintln!("{}", sanitize_output(&String::from_utf8(out.stdout)?)),
-                Err(e) => eprintln!("Error: {e:?}"),
-            }
+const RECORD_NAME: &str = "init.toml";
+
+/// Loads the configs of `dirs`, with the hooks installing `[packages]` ahead
+/// of the declared init hooks.
+fn init_configs(dirs: &[PathBuf]) -> Result<Vec<(PathBuf, Config)>> {
+    let mut configs = ordered_configs(dirs)?;
+    for (_, conf) in &mut configs {
+        if let Some(packages) = &conf.packages {
+            let mut hooks = package_hooks(packages);
+            hooks.extend(conf.init.take().unwrap_or_default());
+            conf.init = Some(hooks);
+        }
+    }
+    Ok(configs)
+}
+
+fn run_init(dirs: &[PathBuf], report: &mut Report) -> Result<()> {
+    let configs = init_configs(dirs)?;
+    for (i, hook) in ordered_hooks(&configs, |c| c.init.as_ref())? {
+        let (base, conf) = &configs[i];
+        if !report.run_hooks(conf, slice::from_ref(hook), base)? {
+            break;
         }
     }
     Ok(())
@@ -36,17 +38,35 @@ fn run_init(base: &Path) -> Result<()> {
 #[test]
 fn test_run_init() -> Result<()> {
     let test_base = PathBuf::from("test/repo/bash");
-    run_init(&test_base)?;
-    let file_path = PathBuf::from("testtouch");
+    let mut report = Report::default();
+    run_init(slice::from_ref(&test_base), &mut report)?;
+    report.finish()?;
+    let file_path = test_base.join("testtouch");
     assert!(file_path.exists());
     std::fs::remove_file(&file_path)?;
     assert!(!file_path.exists());
     Ok(())
 }
 
-pub fn run_inits(dirs: &[PathBuf]) -> Result<()> {
-    for dir in dirs {
-        run_init(dir)?
+/// Prints the init hooks of `dirs` as a shell script instead of running them.
+pub fn print_inits(dirs: &[PathBuf]) -> Result<()> {
+    let configs = init_configs(dirs)?;
+    println!("#!/bin/sh");
+    println!("set -e");
+    for (i, hook) in ordered_hooks(&configs, |c| c.init.as_ref())? {
+        if hook.applies() {
+            let (base, conf) = &configs[i];
+            println!("{}", render(conf, hook, base)?);
+        }
     }
     Ok(())
 }
+
+pub fn run_inits(dirs: &[PathBuf], force: bool) -> Result<()> {
+    let mut report = match state_dir() {
+        Some(state) => Report::with_record(state.join(RECORD_NAME), force)?,
+        None => Report::default(),
+    };
+    run_init(dirs, &mut report)?;
+    report.finish()
+}
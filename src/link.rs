@@ -1,6 +1,11 @@
-use crate::backup::{backup, get_backuppath};
+use crate::backup::{BackupDir, backup};
+use crate::compare::compare_dirs;
+use crate::config::get_config;
+use crate::hook::run_lifecycle;
 use crate::list::list_items;
-use crate::structs::display_path;
+use crate::naming::apply_mode;
+use crate::prompt;
+use crate::structs::{Link, display_path};
 use anyhow::Result;
 use colored::Colorize;
 use glob::glob;
@@ -17,9 +22,81 @@ fn target_is_missing(path: &Path) -> Result<bool> {
     }
 }
 
-fn link(base: &Path, backupdir: &Path) -> Result<()> {
-    for link in list_items(base, false)? {
+fn is_linked(link: &Link) -> bool {
+    fs::read_link(&link.target).is_ok_and(|readlink| readlink == link.source)
+}
+
+/// Copies `from` to `to` recursively, recreating symlinks as symlinks.
+fn copy_tree(from: &Path, to: &Path) -> Result<()> {
+    let meta = fs::symlink_metadata(from)?;
+    if meta.file_type().is_symlink() {
+        unix::fs::symlink(fs::read_link(from)?, to)?;
+    } else if meta.is_dir() {
+        fs::create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_tree(&entry.path(), &to.join(entry.file_name()))?;
+        }
+    } else {
+        fs::copy(from, to)?;
+    }
+    Ok(())
+}
+
+/// Offers to copy the entries only in an existing target directory into the
+/// repo before it is replaced by a link, keeping the repo's version of files
+/// in both.
+fn merge_into_repo(link: &Link, merge: bool) -> Result<()> {
+    let is_real_dir = fs::symlink_metadata(&link.target).is_ok_and(|meta| meta.is_dir());
+    if !link.is_dir || !is_real_dir {
+        return Ok(());
+    }
+    let comparison = compare_dirs(&link.source, &link.target)?;
+    if comparison.only_target.is_empty() {
+        return Ok(());
+    }
+    let question = format!(
+        "Merge {} entries only in {} into the repo?",
+        comparison.only_target.len(),
+        display_path(&link.target)
+    );
+    if !merge && !prompt::confirm(&question)? {
+        return Ok(());
+    }
+    for path in &comparison.only_target {
+        copy_tree(&link.target.join(path), &link.source.join(path))?;
+        eprintln!(
+            "{} {}",
+            "MERGED:".green(),
+            display_path(&link.source.join(path))
+        );
+    }
+    for path in &comparison.differing {
+        eprintln!(
+            "{} {} (kept the repo version)",
+            "CONFLICT:".yellow(),
+            display_path(&link.source.join(path))
+        );
+    }
+    Ok(())
+}
+
+fn link(base: &Path, backupdir: &BackupDir, merge: bool) -> Result<()> {
+    let conf = get_config(base)?;
+    let items = list_items(base, false)?;
+    let pending = items
+        .iter()
+        .filter(|link| !is_linked(link))
+        .map(|link| link.target.clone())
+        .collect::<Vec<_>>();
+    run_lifecycle(conf.as_ref(), |c| c.pre_link.as_ref(), base, &pending)?;
+    let mut changed = vec![];
+    for link in items {
         fs::create_dir_all(link.target.parent().unwrap_or_else(|| Path::new("/")))?;
+        // A symlink carries the permissions of its source.
+        if let Some(mode) = link.mode {
+            apply_mode(&link.source, mode, link.is_dir)?;
+        }
         if let Ok(readlink) = fs::read_link(&link.target) {
             if readlink == link.source {
                 eprintln!("{} {link} (exists)", "SKIPPED:".cyan());
@@ -37,13 +114,15 @@ fn link(base: &Path, backupdir: &Path) -> Result<()> {
                 backup(backupdir, &link.target)?;
             }
         } else if link.target.exists() {
+            merge_into_repo(&link, merge)?;
             eprintln!("{} {}", "BACKUP:".yellow(), display_path(&link.target));
             backup(backupdir, &link.target)?;
         }
         unix::fs::symlink(&link.source, &link.target)?;
         eprintln!("{} {}", "LINKED:".green(), &link);
+        changed.push(link.target);
     }
-    Ok(())
+    run_lifecycle(conf.as_ref(), |c| c.post_link.as_ref(), base, &changed)
 }
 
 fn cleanup_dir(d: Option<&Path>) -> Result<()> {
@@ -59,24 +138,29 @@ fn cleanup_dir(d: Option<&Path>) -> Result<()> {
 }
 
 fn unlink(base: &Path) -> Result<()> {
-    for link in list_items(base, false)? {
-        if link.target.exists()
-            && let Ok(readlink) = fs::read_link(&link.target)
-            && readlink == link.source
-        {
-            eprintln!("{} {link} (exists)", "UNLINK:".cyan());
-            fs::remove_file(&link.target)?;
-            cleanup_dir(link.target.parent())?;
-        }
+    let conf = get_config(base)?;
+    let linked = list_items(base, false)?
+        .into_iter()
+        .filter(|link| link.target.exists() && is_linked(link))
+        .collect::<Vec<_>>();
+    let targets = linked
+        .iter()
+        .map(|link| link.target.clone())
+        .collect::<Vec<_>>();
+    run_lifecycle(conf.as_ref(), |c| c.pre_unlink.as_ref(), base, &targets)?;
+    for link in &linked {
+        eprintln!("{} {link} (exists)", "UNLINK:".cyan());
+        fs::remove_file(&link.target)?;
+        cleanup_dir(link.target.parent())?;
     }
-    Ok(())
+    run_lifecycle(conf.as_ref(), |c| c.post_unlink.as_ref(), base, &targets)
 }
 
 #[test]
 fn test_link() -> Result<()> {
     let test_base = PathBuf::from("test/repo/bash");
-    let test_backupdir = &PathBuf::from("test/backup");
-    link(&test_base, test_backupdir)?;
+    let test_backupdir = &BackupDir::at(Path::new("test/backup"));
+    link(&test_base, test_backupdir, false)?;
     let link_path = PathBuf::from("test/home/.bashrc");
     assert!(link_path.exists());
     assert!(fs::read_link(&link_path).is_ok());
@@ -85,10 +169,10 @@ fn test_link() -> Result<()> {
     Ok(())
 }
 
-pub fn link_dirs(dirs: &[PathBuf]) -> Result<()> {
-    let backupdir = get_backuppath()?;
+pub fn link_dirs(dirs: &[PathBuf], merge: bool) -> Result<()> {
+    let backupdir = BackupDir::session();
     for dir in dirs {
-        link(dir, &backupdir)?
+        link(dir, &backupdir, merge)?
     }
     Ok(())
 }
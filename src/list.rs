@@ -1,24 +1,30 @@
 use crate::{
-    CONFFILE_NAME, IGNOREFILE_NAME, Link, config::get_config, dest::get_dest, structs::display_path,
+    Link,
+    config::{IgnoreSource, get_config},
+    dest::get_dest,
+    filter::Filter,
+    naming,
+    structs::display_path,
 };
 use anyhow::Result;
 use colored::Colorize;
-use ignore::{DirEntry, WalkBuilder};
+use ignore::WalkBuilder;
 use std::collections::HashSet;
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-fn list_diritems(base: &Path) -> Result<HashSet<PathBuf>> {
+pub fn list_diritems(base: &Path) -> Result<HashSet<PathBuf>> {
     let mut items = HashSet::new();
     for d in get_config(base)?.and_then(|c| c.dirs).unwrap_or_default() {
-        let Ok(full) = base.join(&d).canonicalize() else {
+        let Ok(full) = d.canonicalize() else {
             continue;
         };
         if !fs::metadata(full)?.is_dir() {
             continue;
         }
-        items.insert(base.join(d));
+        items.insert(d);
     }
     Ok(items)
 }
@@ -32,15 +38,6 @@ fn test_list_diritems() -> Result<()> {
     Ok(())
 }
 
-fn filter_ignores(e: &DirEntry) -> bool {
-    let p = e.path().file_name().unwrap_or_default().to_string_lossy();
-    !(p == CONFFILE_NAME
-        || p == IGNOREFILE_NAME
-        || p == ".git"
-        || p == ".gitignore"
-        || p == ".gitmodules")
-}
-
 fn metadata_or_report_broken_link(path: &Path) -> Result<Option<fs::Metadata>> {
     match fs::metadata(path) {
         Ok(meta) => Ok(Some(meta)),
@@ -69,12 +66,25 @@ fn metadata_or_report_broken_link(path: &Path) -> Result<Option<fs::Metadata>> {
 
 fn list_dir(base: &Path, dir: &Path, dir_items: &HashSet<PathBuf>) -> Result<Vec<Link>> {
     let mut items = vec![];
+    let conf = get_config(base)?;
+    let dotted = conf
+        .as_ref()
+        .and_then(|c| c.dotted_names)
+        .unwrap_or_default();
+    let filter = Arc::new(Filter::new(base, conf.as_ref())?);
+    let honors = |source| conf.as_ref().is_none_or(|c| c.honors(source));
     let pat = dir.to_str().unwrap_or_default().to_string();
     'walk: for r in WalkBuilder::new(pat)
         .standard_filters(true)
         .hidden(false)
-        .add_custom_ignore_filename(IGNOREFILE_NAME)
-        .filter_entry(filter_ignores)
+        .git_ignore(honors(IgnoreSource::Git))
+        .git_exclude(honors(IgnoreSource::Git))
+        .git_global(honors(IgnoreSource::GlobalGit))
+        .ignore(honors(IgnoreSource::Ignore))
+        .filter_entry(move |e| {
+            let is_dir = e.file_type().is_some_and(|t| t.is_dir());
+            filter.keeps(e.path(), is_dir)
+        })
         .build()
     {
         match r {
@@ -84,6 +94,20 @@ fn list_dir(base: &Path, dir: &Path, dir_items: &HashSet<PathBuf>) -> Result<Vec
                     continue;
                 };
                 let f = p.strip_prefix(base).unwrap_or(&p);
+                if conf.as_ref().is_some_and(|c| !c.manages(f)) {
+                    continue;
+                }
+                let (f, mode) = if dotted {
+                    match naming::decode(f) {
+                        Ok(decoded) => decoded,
+                        Err(err) => {
+                            eprintln!("{} {err}", "ERROR:".red());
+                            continue;
+                        }
+                    }
+                } else {
+                    (f.to_path_buf(), None)
+                };
                 let dst = get_dest(&p)?.canonicalize()?.join(f);
                 if meta.is_file() {
                     for dir_item in dir_items {
@@ -91,9 +115,9 @@ fn list_dir(base: &Path, dir: &Path, dir_items: &HashSet<PathBuf>) -> Result<Vec
                             continue 'walk;
                         }
                     }
-                    items.push(Link::new(p.canonicalize()?, dst, false));
+                    items.push(Link::new(p.canonicalize()?, dst, false).with_mode(mode));
                 } else if meta.is_dir() && dir_items.contains(&p) {
-                    items.push(Link::new(p.canonicalize()?, dst, true));
+                    items.push(Link::new(p.canonicalize()?, dst, true).with_mode(mode));
                 }
             }
             Err(err) => println!("{err:?}"),
@@ -4,20 +4,34 @@ use clap_complete::{generate, shells};
 use std::path::{Component, Path, PathBuf};
 
 mod backup;
+mod compare;
+mod condition;
 mod config;
 mod copy;
 mod dest;
+mod diff;
+mod explain;
+mod filter;
+mod git;
+mod hook;
 mod init;
 mod link;
 mod list;
+mod naming;
+mod order;
+mod packages;
+mod platform;
+mod prompt;
 mod pull;
+mod record;
+mod render;
 mod repo;
 mod show;
 mod structs;
 mod update;
 mod wget;
 
-use structs::{Content, Link};
+use structs::Link;
 
 const CONFFILE_NAME: &str = ".wagon.toml";
 const IGNOREFILE_NAME: &str = ".wagonignore";
@@ -53,7 +67,7 @@ enum Command {
     ///
     /// For each file in the repo, copy to the destination (from config.dest in
     /// .wagon.toml or your home directory by default). Existing files are backed
-    /// up into .backups/uid<uid>/YYYY/MM/DD/HH:MM:SS before being overwritten.
+    /// up into <backup_dir>/uid<uid>/YYYY/MM/DD/HH:MM:SS before being overwritten.
     #[clap(alias = "cp")]
     Copy {
         /// One or more subdirectories under the base to process.
@@ -70,6 +84,11 @@ enum Command {
         /// One or more subdirectories under the base to process.
         /// Defaults to base when omitted.
         dir: Vec<PathBuf>,
+
+        /// Copy entries only in existing target directories into the repo
+        /// before linking them, without asking.
+        #[clap(long)]
+        merge: bool,
     },
 
     /// Remove symlinks previously created by `link`.
@@ -92,26 +111,97 @@ enum Command {
         /// One or more subdirectories under the base to inspect.
         /// Defaults to base when omitted.
         dir: Vec<PathBuf>,
+
+        /// Show diffs with an external command, e.g. `delta`, `difft` or
+        /// `git diff --no-index`. The source and target paths are appended
+        /// unless the command has `{source}` and `{target}` placeholders,
+        /// and the `--context` lines are passed by a `{context}` placeholder.
+        #[clap(long, value_name = "CMD", conflicts_with_all = ["no_diff", "stat"])]
+        diff_tool: Option<String>,
+
+        /// Lines of context around changes [default: 3]. A diff tool only
+        /// gets them through its `{context}` placeholder.
+        #[clap(short = 'U', long, value_name = "N")]
+        context: Option<usize>,
+
+        /// List links without showing diffs of existing files.
+        #[clap(long, conflicts_with = "stat")]
+        no_diff: bool,
+
+        /// Show the number of added and removed lines instead of diffs.
+        #[clap(long)]
+        stat: bool,
+
+        /// Only show links with these statuses.
+        #[clap(long, value_enum, value_delimiter = ',', value_name = "STATUS")]
+        only: Vec<show::Status>,
+
+        /// Hide links with these statuses.
+        #[clap(long, value_enum, value_delimiter = ',', value_name = "STATUS")]
+        exclude_status: Vec<show::Status>,
+
+        /// Only show links whose source, relative to the directory, or
+        /// target matches the glob. May be repeated.
+        #[clap(short, long = "path", value_name = "GLOB")]
+        paths: Vec<String>,
+
+        /// Print the number of links per status instead of the links.
+        #[clap(long, conflicts_with_all = ["diff_tool", "stat"])]
+        summary: bool,
+
+        /// Lay links out as flat lines with diffs, a tree grouped by
+        /// destination directory, or a table with sizes and mtimes.
+        #[clap(long, value_enum, conflicts_with_all = ["diff_tool", "stat", "summary"])]
+        format: Option<render::Format>,
     },
 
     /// Run init hooks from .wagon.toml.
     ///
     /// Executes commands defined in the `init` section for the current OS.
-    /// Useful for one-time setup steps after cloning the repo.
+    /// Hooks that already succeeded are recorded and skipped on later runs,
+    /// so init is safe to run repeatedly.
     Init {
         /// One or more subdirectories under the base that contain .wagon.toml.
         /// Defaults to base when omitted.
         dir: Vec<PathBuf>,
+
+        /// Rerun hooks even if they already succeeded.
+        #[clap(long)]
+        force: bool,
+
+        /// Print the hooks, including package installs, as a shell script
+        /// instead of running them.
+        #[clap(long, conflicts_with = "force")]
+        print: bool,
     },
 
     /// Run update hooks from .wagon.toml.
     ///
     /// Executes commands defined in the `update` section for the current OS.
-    /// Useful for routine updates of generated configs.
+    /// Useful for routine updates of generated configs. Prints a summary of
+    /// durations and statuses when several directories are updated.
     Update {
         /// One or more subdirectories under the base that contain .wagon.toml.
         /// Defaults to base when omitted.
         dir: Vec<PathBuf>,
+
+        /// Number of directories to update concurrently.
+        #[clap(short, long, default_value_t = 1, value_name = "N")]
+        jobs: usize,
+    },
+
+    /// Explain why a file is or is not managed.
+    ///
+    /// Takes a path in the repo or at the destination and shows the
+    /// .wagon.toml chosen and skipped, the destination, the ignore rule or
+    /// `dirs` item applying to it, and the target it is linked to.
+    Explain {
+        path: PathBuf,
+
+        /// Subdirectories under the base to look in. May be repeated.
+        /// Defaults to base when omitted.
+        #[clap(short, long)]
+        dir: Vec<PathBuf>,
     },
 
     /// Pull existing files from destination back into the repo.
@@ -126,21 +216,48 @@ enum Command {
 
     /// Clone a repository to the configured src directory.
     ///
-    /// Accepts full https URLs (https://<site>/<owner>/<repo>[.git]) or shorthands
-    /// like gh:owner/repo, gl:group/project, bb:team/repo. Repos are placed under
-    /// ~/<src>/<site>/<owner>/<repo> and the path is printed.
+    /// Accepts https, ssh, git and file URLs, scp-style git@<site>:<owner>/<repo>,
+    /// shorthands like gh:owner/repo, gl:group/project, bb:team/repo (plus those
+    /// defined in the global config) and plain owner/repo for GitHub. Repos are
+    /// placed under ~/<src>/<site>/<owner>/<repo> and the path is printed.
+    #[clap(args_conflicts_with_subcommands = true)]
     Repo {
+        #[clap(subcommand)]
+        cmd: Option<RepoCommand>,
+
         /// One or more repository identifiers (URL or shorthand).
         pathlikes: Vec<String>,
+
+        #[clap(flatten)]
+        clone: CloneArgs,
+    },
+
+    /// List or prune the backups made by link and copy.
+    ///
+    /// Backups are kept in `backup_dir` of the global config, by default
+    /// $XDG_STATE_HOME/wagon/backups, with one session per run. Lists the
+    /// sessions with their file counts and sizes when no subcommand is given.
+    Backups {
+        #[clap(subcommand)]
+        cmd: Option<BackupsCommand>,
     },
 
-    /// Mirror a website subtree into the configured src directory using wget.
+    /// Mirror a website subtree into the configured src directory.
     ///
-    /// Requires `wget` to be installed. Runs `wget -r <url>` with current dir set
-    /// to the configured GlobalConfig.src path.
+    /// Documents are saved under ~/<src>/<host>/<path>. http and file URLs are
+    /// fetched by wagon itself, other schemes by `wget`. The `fetcher` command
+    /// template of the global config replaces both.
     Wget {
         /// The URL to mirror recursively.
         url: String,
+
+        /// Maximum depth of followed links, 0 for no limit.
+        #[clap(short, long, default_value_t = 5, value_name = "N")]
+        level: u32,
+
+        /// Do not follow links above the directory of the URL.
+        #[clap(long)]
+        no_parent: bool,
     },
 
     /// Generate shell completion scripts for your shell.
@@ -152,6 +269,134 @@ enum Command {
     },
 }
 
+/// Options of `git clone`, overriding the `[clone]` table of the global config.
+#[derive(Debug, clap::Args)]
+struct CloneArgs {
+    /// Create a shallow clone with this many commits.
+    #[clap(long, value_name = "N")]
+    depth: Option<u32>,
+
+    /// Check out this branch instead of the remote's HEAD.
+    #[clap(long, value_name = "NAME")]
+    branch: Option<String>,
+
+    /// Also clone the submodules.
+    #[clap(long)]
+    recurse_submodules: bool,
+
+    /// Create a bare repository without a working tree.
+    #[clap(long, conflicts_with = "worktree")]
+    bare: bool,
+
+    /// Create a bare repository in `.bare` and check out the branch as a
+    /// worktree next to it.
+    #[clap(long)]
+    worktree: bool,
+
+    /// Run the init hooks of the cloned repository without asking.
+    #[clap(long, conflicts_with = "no_init")]
+    init: bool,
+
+    /// Do not run or offer to run the init hooks of the cloned repository.
+    #[clap(long)]
+    no_init: bool,
+}
+
+impl CloneArgs {
+    fn options(&self) -> config::CloneOptions {
+        config::CloneOptions {
+            depth: self.depth,
+            branch: self.branch.clone(),
+            recurse_submodules: self.recurse_submodules.then_some(true),
+            layout: match (self.bare, self.worktree) {
+                (true, _) => Some(config::Layout::Bare),
+                (_, true) => Some(config::Layout::Worktree),
+                _ => None,
+            },
+        }
+    }
+
+    fn init(&self) -> Option<bool> {
+        match (self.init, self.no_init) {
+            (true, _) => Some(true),
+            (_, true) => Some(false),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Parser)]
+#[clap(rename_all = "kebab-case")]
+enum RepoCommand {
+    /// Clone repositories, the same as `wagon repo <PATHLIKES>...`.
+    #[clap(alias = "clone")]
+    Checkout {
+        /// One or more repository identifiers (URL or shorthand).
+        #[clap(required = true)]
+        pathlikes: Vec<String>,
+
+        #[clap(flatten)]
+        clone: CloneArgs,
+    },
+
+    /// List the cloned repositories with their branch and state.
+    ///
+    /// Shows whether the working tree has uncommitted changes and how far
+    /// the branch is ahead of or behind its upstream, as of the last fetch.
+    #[clap(alias = "ls")]
+    List,
+
+    /// Print the path of the cloned repository best matching a pattern.
+    ///
+    /// Exact repository names win over substrings of the name or the path,
+    /// which win over fuzzy matches, e.g. `cd "$(wagon repo path wagon)"`.
+    Path {
+        /// Part of the repository name or path.
+        pattern: String,
+    },
+
+    /// Clone or fast-forward the repositories of the manifest.
+    ///
+    /// Reads `repos` and `repos_file` from the global config. Missing
+    /// repositories are cloned, existing ones are fetched and fast-forwarded.
+    /// Dirty or diverged working trees are reported and left untouched.
+    Sync {
+        /// Read the repositories from this file instead, one per line.
+        #[clap(long, value_name = "PATH")]
+        file: Option<PathBuf>,
+
+        /// Number of repositories to sync concurrently.
+        #[clap(short, long, default_value_t = 4, value_name = "N")]
+        jobs: usize,
+    },
+}
+
+#[derive(Debug, Parser)]
+#[clap(rename_all = "kebab-case")]
+enum BackupsCommand {
+    /// List the backup sessions with their file counts and sizes.
+    #[clap(alias = "ls")]
+    List,
+
+    /// Remove old backup sessions.
+    ///
+    /// A session is kept when any of the given rules keeps it.
+    #[clap(group(clap::ArgGroup::new("retention").required(true).multiple(true)))]
+    Prune {
+        /// Keep the newest N sessions.
+        #[clap(long, value_name = "N", group = "retention")]
+        keep_last: Option<usize>,
+
+        /// Keep the sessions younger than this, e.g. `30d` or `12h`.
+        #[clap(long, value_name = "DURATION", value_parser = config::parse_duration, group = "retention")]
+        older_than: Option<std::time::Duration>,
+
+        /// Print the sessions that would be removed without removing them.
+        #[clap(long)]
+        dry_run: bool,
+    },
+}
+
 #[allow(clippy::enum_variant_names)]
 #[derive(Debug, Parser)]
 #[clap(rename_all = "kebab-case")]
@@ -224,21 +469,86 @@ fn main() -> Result<()> {
     let base = opt.base.unwrap_or_else(|| current_dir.clone());
     match command {
         Command::Copy { dir } => copy::copy_dirs(&resolve_dirs(&base, dir))?,
-        Command::Link { dir } => link::link_dirs(&resolve_dirs(&base, dir))?,
+        Command::Link { dir, merge } => link::link_dirs(&resolve_dirs(&base, dir), merge)?,
         Command::Unlink { dir } => link::unlink_dirs(&resolve_dirs(&base, dir))?,
-        Command::List { dir } => show::show_list(&resolve_dirs(&base, dir))?,
-        Command::Init { dir } => init::run_inits(&resolve_dirs(&base, dir))?,
-        Command::Update { dir } => update::run_updates(&resolve_dirs(&base, dir))?,
+        Command::List {
+            dir,
+            diff_tool,
+            context,
+            no_diff,
+            stat,
+            only,
+            exclude_status,
+            paths,
+            summary,
+            format,
+        } => show::show_list(
+            &resolve_dirs(&base, dir),
+            show::ListOptions {
+                context,
+                diff_tool,
+                no_diff,
+                stat,
+                max_diff_size: None,
+                only,
+                exclude_status,
+                paths,
+                summary,
+                format: format.unwrap_or_default(),
+            },
+        )?,
+        Command::Init {
+            dir,
+            force: _,
+            print: true,
+        } => init::print_inits(&resolve_dirs(&base, dir))?,
+        Command::Init { dir, force, .. } => init::run_inits(&resolve_dirs(&base, dir), force)?,
+        Command::Update { dir, jobs } => update::run_updates(&resolve_dirs(&base, dir), jobs)?,
+        Command::Explain { path, dir } => explain::explain(&resolve_dirs(&base, dir), &path)?,
         Command::Pull { target } => pull::pull_files(&base, &current_dir, &target)?,
-        Command::Repo { pathlikes } => {
-            for pathlike in pathlikes {
-                if pathlike == "checkout" {
-                    continue;
-                }
-                repo::load_repo(&pathlike)?;
-            }
+        Command::Repo {
+            cmd: Some(RepoCommand::Sync { file, jobs }),
+            ..
+        } => repo::sync_repos(file.as_deref(), jobs)?,
+        Command::Repo {
+            cmd: Some(RepoCommand::List),
+            ..
+        } => repo::list_repos()?,
+        Command::Repo {
+            cmd: Some(RepoCommand::Path { pattern }),
+            ..
+        } => repo::print_repo_path(&pattern)?,
+        Command::Repo {
+            cmd: Some(RepoCommand::Checkout { pathlikes, clone }),
+            ..
         }
-        Command::Wget { url } => wget::wget(&url)?,
+        | Command::Repo {
+            cmd: None,
+            pathlikes,
+            clone,
+        } => repo::load_repos(&pathlikes, clone.options(), clone.init())?,
+        Command::Backups {
+            cmd: None | Some(BackupsCommand::List),
+        } => backup::list_backups()?,
+        Command::Backups {
+            cmd:
+                Some(BackupsCommand::Prune {
+                    keep_last,
+                    older_than,
+                    dry_run,
+                }),
+        } => backup::prune_backups(
+            &backup::Retention {
+                keep_last,
+                older_than,
+            },
+            dry_run,
+        )?,
+        Command::Wget {
+            url,
+            level,
+            no_parent,
+        } => wget::wget(&url, wget::Limits { level, no_parent })?,
         Command::Completion { shell } => generate_completion(shell),
     }
     Ok(())
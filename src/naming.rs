@@ -0,0 +1,206 @@
+use crate::structs::{display_path, sanitize_display};
+use anyhow::{Result, bail};
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Component, Path, PathBuf};
+
+const DOT_PREFIX: &str = "dot_";
+const PRIVATE_PREFIX: &str = "private_";
+const EXECUTABLE_PREFIX: &str = "executable_";
+/// Ends the prefixes, keeping the rest of the name as is.
+const LITERAL_PREFIX: &str = "literal_";
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Attributes {
+    private: bool,
+    executable: bool,
+}
+
+impl Attributes {
+    fn mode(&self) -> Option<u32> {
+        match (self.private, self.executable) {
+            (false, false) => None,
+            (true, false) => Some(0o600),
+            (false, true) => Some(0o755),
+            (true, true) => Some(0o700),
+        }
+    }
+}
+
+fn decode_name(name: &str) -> (String, Attributes) {
+    let mut attrs = Attributes::default();
+    let mut rest = name;
+    loop {
+        if let Some(r) = rest.strip_prefix(PRIVATE_PREFIX) {
+            attrs.private = true;
+            rest = r;
+        } else if let Some(r) = rest.strip_prefix(EXECUTABLE_PREFIX) {
+            attrs.executable = true;
+            rest = r;
+        } else {
+            break;
+        }
+    }
+    let name = if let Some(r) = rest.strip_prefix(LITERAL_PREFIX) {
+        r.to_owned()
+    } else if let Some(r) = rest.strip_prefix(DOT_PREFIX) {
+        format!(".{r}")
+    } else {
+        rest.to_owned()
+    };
+    (name, attrs)
+}
+
+/// Translates a repo-relative path written with `dot_`, `private_`,
+/// `executable_` and `literal_` prefixes into the destination-relative path,
+/// together with the mode implied by the prefixes of the last component.
+/// Mode prefixes on parent directories are rejected, as they would not be
+/// applied.
+pub fn decode(path: &Path) -> Result<(PathBuf, Option<u32>)> {
+    let count = path.components().count();
+    let mut decoded = PathBuf::new();
+    let mut mode = None;
+    for (i, component) in path.components().enumerate() {
+        match component {
+            Component::Normal(name) => match name.to_str() {
+                Some(name) => {
+                    let (decoded_name, attrs) = decode_name(name);
+                    mode = attrs.mode();
+                    if mode.is_some() && i + 1 < count {
+                        bail!(
+                            "mode prefix of directory `{}` in {} is not applied; set it on the files instead",
+                            sanitize_display(name),
+                            display_path(path)
+                        );
+                    }
+                    decoded.push(decoded_name);
+                }
+                None => {
+                    decoded.push(name);
+                    mode = None;
+                }
+            },
+            other => decoded.push(other.as_os_str()),
+        }
+    }
+    Ok((decoded, mode))
+}
+
+/// Reverses [`decode`] for a destination-relative path, choosing the
+/// `private_`/`executable_` prefixes of the last component from `mode` and
+/// escaping names that start with a prefix with `literal_`.
+pub fn encode(path: &Path, mode: Option<u32>) -> PathBuf {
+    let count = path.components().count();
+    let mut encoded = PathBuf::new();
+    for (i, component) in path.components().enumerate() {
+        let Component::Normal(name) = component else {
+            encoded.push(component.as_os_str());
+            continue;
+        };
+        let mut prefixed = OsString::new();
+        if i + 1 == count
+            && let Some(mode) = mode
+        {
+            if mode & 0o077 == 0 {
+                prefixed.push(PRIVATE_PREFIX);
+            }
+            if mode & 0o111 != 0 {
+                prefixed.push(EXECUTABLE_PREFIX);
+            }
+        }
+        let text = name.to_str().unwrap_or_default();
+        if let Some(rest) = text.strip_prefix('.') {
+            prefixed.push(DOT_PREFIX);
+            prefixed.push(rest);
+        } else {
+            let prefixes = [
+                DOT_PREFIX,
+                PRIVATE_PREFIX,
+                EXECUTABLE_PREFIX,
+                LITERAL_PREFIX,
+            ];
+            if prefixes.iter().any(|prefix| text.starts_with(prefix)) {
+                prefixed.push(LITERAL_PREFIX);
+            }
+            prefixed.push(name);
+        }
+        encoded.push(prefixed);
+    }
+    encoded
+}
+
+/// Applies a mode decoded from the name prefixes, adding search permission
+/// for directories wherever read permission is granted.
+pub fn apply_mode(path: &Path, mode: u32, is_dir: bool) -> io::Result<()> {
+    let mode = if is_dir {
+        mode | (mode & 0o444) >> 2
+    } else {
+        mode
+    };
+    let current = fs::metadata(path)?.permissions();
+    if current.mode() & 0o7777 == mode {
+        return Ok(());
+    }
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_translates_dot_prefix_in_every_component() {
+        assert_eq!(
+            decode(Path::new("dot_config/git/dot_gitignore")).unwrap(),
+            (PathBuf::from(".config/git/.gitignore"), None)
+        );
+    }
+
+    #[test]
+    fn decode_reads_modes_from_last_component() {
+        assert_eq!(
+            decode(Path::new("dot_ssh/private_config")).unwrap(),
+            (PathBuf::from(".ssh/config"), Some(0o600))
+        );
+        assert_eq!(
+            decode(Path::new("bin/executable_hello")).unwrap(),
+            (PathBuf::from("bin/hello"), Some(0o755))
+        );
+        assert_eq!(
+            decode(Path::new("private_executable_dot_hook")).unwrap(),
+            (PathBuf::from(".hook"), Some(0o700))
+        );
+    }
+
+    #[test]
+    fn encode_reverses_decode() {
+        for (path, mode) in [
+            (".bashrc", None),
+            (".ssh/config", Some(0o600)),
+            ("bin/.hook", Some(0o700)),
+            ("bin/hello", Some(0o755)),
+            ("dot_config/dot_vimrc", None),
+            ("private_notes/literal_x", Some(0o600)),
+            (".config/executable_tool", Some(0o755)),
+        ] {
+            let encoded = encode(Path::new(path), mode);
+            assert_eq!(decode(&encoded).unwrap(), (PathBuf::from(path), mode));
+        }
+        assert_eq!(
+            encode(Path::new("dot_vimrc"), None),
+            PathBuf::from("literal_dot_vimrc")
+        );
+    }
+
+    #[test]
+    fn decode_rejects_mode_prefixes_on_directories() {
+        let err = decode(Path::new("private_dot_ssh/config")).unwrap_err();
+        assert!(err.to_string().contains("`private_dot_ssh`"));
+        assert_eq!(
+            decode(Path::new("dot_ssh/literal_private_x")).unwrap(),
+            (PathBuf::from(".ssh/private_x"), None)
+        );
+    }
+}
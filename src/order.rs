@@ -0,0 +1,222 @@
+use crate::config::{Command, Config, get_config};
+use crate::structs::display_path;
+use anyhow::{Result, bail};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+use std::path::PathBuf;
+
+/// Orders `0..len` so that `a` comes before `b` for every edge `(a, b)`,
+/// keeping the original order wherever it is unconstrained. On a cycle the
+/// nodes that could not be ordered are returned instead.
+pub fn toposort(len: usize, edges: &[(usize, usize)]) -> Result<Vec<usize>, Vec<usize>> {
+    let mut indegree = vec![0; len];
+    let mut successors = vec![vec![]; len];
+    for &(a, b) in edges {
+        successors[a].push(b);
+        indegree[b] += 1;
+    }
+    let mut ready = (0..len)
+        .filter(|&i| indegree[i] == 0)
+        .map(Reverse)
+        .collect::<BinaryHeap<_>>();
+    let mut order = Vec::with_capacity(len);
+    while let Some(Reverse(i)) = ready.pop() {
+        order.push(i);
+        for &j in &successors[i] {
+            indegree[j] -= 1;
+            if indegree[j] == 0 {
+                ready.push(Reverse(j));
+            }
+        }
+    }
+    if order.len() == len {
+        Ok(order)
+    } else {
+        Err((0..len).filter(|&i| indegree[i] > 0).collect())
+    }
+}
+
+/// Loads the configs of `dirs` together with the directories they depend on,
+/// ordered so that dependencies come first.
+pub fn ordered_configs(dirs: &[PathBuf]) -> Result<Vec<(PathBuf, Config)>> {
+    let mut entries: Vec<(PathBuf, PathBuf, Option<Config>)> = vec![];
+    let mut queue = dirs.iter().cloned().collect::<VecDeque<_>>();
+    while let Some(dir) = queue.pop_front() {
+        let canonical = dir.canonicalize().unwrap_or(dir.clone());
+        if entries.iter().any(|(_, c, _)| *c == canonical) {
+            continue;
+        }
+        let conf = get_config(&dir)?;
+        if let Some(conf) = &conf {
+            queue.extend(conf.depends.iter().flatten().cloned());
+        }
+        entries.push((dir, canonical, conf));
+    }
+    let mut edges = vec![];
+    for (i, (_, _, conf)) in entries.iter().enumerate() {
+        for dep in conf.iter().flat_map(|c| c.depends.iter().flatten()) {
+            let dep = dep.canonicalize().unwrap_or(dep.clone());
+            if let Some(j) = entries.iter().position(|(_, c, _)| *c == dep) {
+                edges.push((j, i));
+            }
+        }
+    }
+    let order = toposort(entries.len(), &edges).or_else(|cycle| {
+        let dirs = cycle
+            .iter()
+            .map(|&i| display_path(&entries[i].0))
+            .collect::<Vec<_>>();
+        bail!("dependency cycle between directories: {}", dirs.join(", "))
+    })?;
+    let mut entries = entries.into_iter().map(Some).collect::<Vec<_>>();
+    Ok(order
+        .into_iter()
+        .filter_map(|i| entries[i].take())
+        .filter_map(|(dir, _, conf)| Some((dir, conf?)))
+        .collect())
+}
+
+/// Orders the hooks selected by `hooks` across `configs`, honoring `after`
+/// and directory dependencies, and otherwise keeping the order of the
+/// configs. Yields the config index with each hook.
+pub fn ordered_hooks(
+    configs: &[(PathBuf, Config)],
+    hooks: fn(&Config) -> Option<&Vec<Command>>,
+) -> Result<Vec<(usize, &Command)>> {
+    let nodes = configs
+        .iter()
+        .enumerate()
+        .flat_map(|(i, (_, conf))| hooks(conf).into_iter().flatten().map(move |h| (i, h)))
+        .collect::<Vec<_>>();
+    let canonical = configs
+        .iter()
+        .map(|(dir, _)| dir.canonicalize().unwrap_or(dir.clone()))
+        .collect::<Vec<_>>();
+    let mut edges = vec![];
+    for (b, (i, hook)) in nodes.iter().enumerate() {
+        for dep in configs[*i].1.depends.iter().flatten() {
+            let dep = dep.canonicalize().unwrap_or(dep.clone());
+            for (a, (j, _)) in nodes.iter().enumerate() {
+                if canonical[*j] == dep {
+                    edges.push((a, b));
+                }
+            }
+        }
+        for name in hook.after.iter().flatten() {
+            let before = nodes
+                .iter()
+                .enumerate()
+                .filter(|(_, (_, h))| h.name.as_ref() == Some(name))
+                .map(|(a, _)| a)
+                .collect::<Vec<_>>();
+            if before.is_empty() {
+                bail!(
+                    "unknown hook `{name}` in `after` of `{}` in {}",
+                    hook.command,
+                    display_path(&configs[*i].1.path)
+                );
+            }
+            edges.extend(before.into_iter().map(|a| (a, b)));
+        }
+    }
+    let order = toposort(nodes.len(), &edges).or_else(|cycle| {
+        let hooks = cycle
+            .iter()
+            .map(|&n| {
+                let (i, hook) = nodes[n];
+                let name = hook.name.as_deref().unwrap_or(&hook.command);
+                format!("{name} ({})", display_path(&configs[i].1.path))
+            })
+            .collect::<Vec<_>>();
+        bail!("dependency cycle between hooks: {}", hooks.join(", "))
+    })?;
+    Ok(order.into_iter().map(|n| nodes[n]).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toposort_keeps_original_order_when_unconstrained() {
+        assert_eq!(toposort(4, &[]), Ok(vec![0, 1, 2, 3]));
+        assert_eq!(toposort(4, &[(3, 1)]), Ok(vec![0, 2, 3, 1]));
+    }
+
+    #[test]
+    fn toposort_reports_cycles() {
+        assert_eq!(toposort(3, &[(0, 1), (1, 2), (2, 1)]), Err(vec![1, 2]));
+    }
+
+    fn config(path: &str, conf: &str) -> (PathBuf, Config) {
+        let mut config = toml::from_str::<Config>(conf).unwrap();
+        config.path = PathBuf::from(path).join(".wagon.toml");
+        (PathBuf::from(path), config)
+    }
+
+    #[test]
+    fn hooks_follow_after_across_configs() -> Result<()> {
+        let configs = [
+            config(
+                "/repo/nvim",
+                r#"
+                [[init]]
+                command = "nvim"
+                after = ["brew", "plug"]
+                [[init]]
+                name = "brew"
+                command = "brew"
+                "#,
+            ),
+            config(
+                "/repo/zsh",
+                r#"
+                [[init]]
+                command = "zsh"
+                [[init]]
+                name = "plug"
+                command = "plug"
+                "#,
+            ),
+        ];
+        let order = ordered_hooks(&configs, |c| c.init.as_ref())?
+            .into_iter()
+            .map(|(_, hook)| hook.command.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(order, ["brew", "zsh", "plug", "nvim"]);
+        Ok(())
+    }
+
+    #[test]
+    fn hook_cycles_and_unknown_names_fail() {
+        let configs = [config(
+            "/repo/nvim",
+            r#"
+            [[init]]
+            name = "a"
+            command = "a"
+            after = ["b"]
+            [[init]]
+            name = "b"
+            command = "b"
+            after = ["a"]
+            "#,
+        )];
+        let err = ordered_hooks(&configs, |c| c.init.as_ref()).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("dependency cycle between hooks: a")
+        );
+
+        let configs = [config(
+            "/repo/nvim",
+            r#"
+            [[init]]
+            command = "a"
+            after = ["missing"]
+            "#,
+        )];
+        let err = ordered_hooks(&configs, |c| c.init.as_ref()).unwrap_err();
+        assert!(err.to_string().starts_with("unknown hook `missing`"));
+    }
+}
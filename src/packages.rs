@@ -0,0 +1,185 @@
+use crate::config::{Command, Packages};
+use crate::platform::{distro_ids, find_executable};
+use std::env::consts;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Manager {
+    Brew,
+    Apt,
+    Dnf,
+    Pacman,
+    Cargo,
+}
+
+impl Manager {
+    fn name(self) -> &'static str {
+        match self {
+            Manager::Brew => "brew",
+            Manager::Apt => "apt",
+            Manager::Dnf => "dnf",
+            Manager::Pacman => "pacman",
+            Manager::Cargo => "cargo",
+        }
+    }
+
+    fn packages(self, packages: &Packages) -> &[String] {
+        let list = match self {
+            Manager::Brew => &packages.brew,
+            Manager::Apt => &packages.apt,
+            Manager::Dnf => &packages.dnf,
+            Manager::Pacman => &packages.pacman,
+            Manager::Cargo => &packages.cargo,
+        };
+        list.as_deref().unwrap_or_default()
+    }
+
+    /// Shell test succeeding when package `$p` is installed.
+    fn installed(self) -> &'static str {
+        match self {
+            Manager::Brew => "brew list --versions \"$p\" >/dev/null 2>&1",
+            Manager::Apt => {
+                "dpkg-query -W -f='${Status}' \"$p\" 2>/dev/null | grep -q 'ok installed'"
+            }
+            Manager::Dnf => "rpm -q \"$p\" >/dev/null 2>&1",
+            Manager::Pacman => "pacman -Q \"$p\" >/dev/null 2>&1",
+            Manager::Cargo => "cargo install --list | grep -q \"^$p \"",
+        }
+    }
+
+    fn install(self) -> &'static str {
+        match self {
+            Manager::Brew => "brew install",
+            Manager::Apt => "$sudo apt-get install -y",
+            Manager::Dnf => "$sudo dnf install -y",
+            Manager::Pacman => "$sudo pacman -S --needed --noconfirm",
+            Manager::Cargo => "cargo install",
+        }
+    }
+
+    fn needs_root(self) -> bool {
+        matches!(self, Manager::Apt | Manager::Dnf | Manager::Pacman)
+    }
+
+    /// Script installing the packages given as positional parameters that are
+    /// not installed yet.
+    fn script(self) -> String {
+        let mut script = String::new();
+        if self.needs_root() {
+            script.push_str("sudo=; [ \"$(id -u)\" -eq 0 ] || sudo=sudo; ");
+        }
+        script.push_str(&format!(
+            "missing=; for p in \"$@\"; do {} || missing=\"$missing $p\"; done; \
+             [ -z \"$missing\" ] || {} $missing",
+            self.installed(),
+            self.install()
+        ));
+        script
+    }
+}
+
+/// The system package manager for the current OS and distribution.
+fn system_manager(os: &str, distro: &[String], has_brew: bool) -> Option<Manager> {
+    if os == "macos" {
+        return Some(Manager::Brew);
+    }
+    let from_distro = distro.iter().find_map(|id| match id.as_str() {
+        "debian" | "ubuntu" => Some(Manager::Apt),
+        "fedora" | "rhel" | "centos" => Some(Manager::Dnf),
+        "arch" => Some(Manager::Pacman),
+        _ => None,
+    });
+    from_distro.or(has_brew.then_some(Manager::Brew))
+}
+
+fn managers(os: &str, distro: &[String], has_brew: bool) -> Vec<Manager> {
+    system_manager(os, distro, has_brew)
+        .into_iter()
+        .chain([Manager::Cargo])
+        .collect()
+}
+
+fn to_hooks(packages: &Packages, managers: &[Manager]) -> Vec<Command> {
+    managers
+        .iter()
+        .filter(|m| !m.packages(packages).is_empty())
+        .map(|m| Command {
+            name: Some(format!("packages.{}", m.name())),
+            command: m.script(),
+            args: m.packages(packages).to_vec(),
+            shell: Some(true),
+            ..Command::default()
+        })
+        .collect()
+}
+
+/// Translates a `[packages]` section into hooks installing the missing
+/// packages with the package managers of the current system.
+pub fn package_hooks(packages: &Packages) -> Vec<Command> {
+    let managers = managers(consts::OS, &distro_ids(), find_executable("brew").is_some());
+    to_hooks(packages, &managers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packages() -> Packages {
+        toml::from_str(
+            r#"
+            brew = ["direnv", "jq"]
+            apt = ["direnv", "jq"]
+            cargo = ["ripgrep"]
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn picks_system_manager_by_os_and_distro() {
+        let ubuntu = ["ubuntu".to_owned(), "debian".to_owned()];
+        assert_eq!(system_manager("macos", &[], false), Some(Manager::Brew));
+        assert_eq!(system_manager("linux", &ubuntu, true), Some(Manager::Apt));
+        assert_eq!(
+            system_manager("linux", &["arch".to_owned()], false),
+            Some(Manager::Pacman)
+        );
+        assert_eq!(
+            system_manager("linux", &["nixos".to_owned()], true),
+            Some(Manager::Brew)
+        );
+        assert_eq!(system_manager("linux", &["nixos".to_owned()], false), None);
+    }
+
+    #[test]
+    fn translates_packages_into_hooks() {
+        let hooks = to_hooks(
+            &packages(),
+            &managers("linux", &["debian".to_owned()], false),
+        );
+        let names = hooks
+            .iter()
+            .map(|h| h.name.as_deref().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["packages.apt", "packages.cargo"]);
+        assert_eq!(hooks[0].args, ["direnv", "jq"]);
+        assert!(
+            hooks[0]
+                .command
+                .contains("$sudo apt-get install -y $missing")
+        );
+        assert!(!hooks[1].command.contains("sudo"));
+    }
+
+    #[test]
+    fn package_script_installs_only_missing_packages() {
+        let script = Manager::Brew
+            .script()
+            .replace("brew list", "false")
+            .replace("brew install", "echo");
+        let output = std::process::Command::new("sh")
+            .args(["-c", &script, "sh", "direnv", "jq"])
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "direnv jq\n");
+    }
+}
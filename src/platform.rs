@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+
+const OS_RELEASE: &str = "/etc/os-release";
+
+/// Key-value pairs of `/etc/os-release`, empty when it is unavailable.
+pub fn os_release() -> HashMap<String, String> {
+    fs::read_to_string(OS_RELEASE)
+        .map(|text| parse_os_release(&text))
+        .unwrap_or_default()
+}
+
+fn parse_os_release(text: &str) -> HashMap<String, String> {
+    text.lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| {
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+                .unwrap_or(value);
+            (key.trim().to_owned(), value.to_owned())
+        })
+        .collect()
+}
+
+/// Distribution ids of the running system: `ID` followed by `ID_LIKE`.
+pub fn distro_ids() -> Vec<String> {
+    let release = os_release();
+    release
+        .get("ID")
+        .into_iter()
+        .chain(release.get("ID_LIKE"))
+        .flat_map(|ids| ids.split_whitespace())
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Looks up an executable by name in `PATH`.
+pub fn find_executable(name: &str) -> Option<PathBuf> {
+    let paths = env::var_os("PATH")?;
+    env::split_paths(&paths)
+        .map(|dir| dir.join(name))
+        .find(|path| {
+            fs::metadata(path)
+                .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_os_release() {
+        let release = parse_os_release(
+            "# comment\nNAME=\"Ubuntu\"\nID=ubuntu\nID_LIKE=debian\nVERSION_ID='24.04'\n",
+        );
+        assert_eq!(release["NAME"], "Ubuntu");
+        assert_eq!(release["ID_LIKE"], "debian");
+        assert_eq!(release["VERSION_ID"], "24.04");
+    }
+
+    #[test]
+    fn finds_executables_on_path() {
+        assert!(find_executable("sh").is_some());
+        assert!(find_executable("wagon-missing-command").is_none());
+    }
+}
@@ -0,0 +1,15 @@
+use anyhow::Result;
+use std::io::{self, BufRead, IsTerminal, Write};
+
+/// Asks a yes/no question on the terminal, answering no when stdin is not a
+/// terminal.
+pub fn confirm(question: &str) -> Result<bool> {
+    if !io::stdin().is_terminal() {
+        return Ok(false);
+    }
+    eprint!("{question} [y/N] ");
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes" | "Yes"))
+}
@@ -1,7 +1,8 @@
-use crate::{config::get_config, structs::display_path};
+use crate::{config::get_config, naming, structs::display_path};
 use anyhow::Result;
 use colored::Colorize;
 use std::fs;
+use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
 
 pub fn absolute_path(value: &str) -> std::result::Result<PathBuf, String> {
@@ -16,9 +17,16 @@ pub fn absolute_path(value: &str) -> std::result::Result<PathBuf, String> {
 pub fn pull_files(base: &Path, dir: &Path, targets: &[PathBuf]) -> Result<()> {
     if let Some(conf) = get_config(&base.join(dir))? {
         let dest = conf.dest.unwrap_or_else(|| dirs::home_dir().unwrap());
+        let dotted = conf.dotted_names.unwrap_or_default();
         for target in targets {
             if target.is_file() {
-                let to = dir.join(target.strip_prefix(&dest)?);
+                let relative = target.strip_prefix(&dest)?;
+                let to = if dotted {
+                    let mode = fs::metadata(target)?.permissions().mode() & 0o777;
+                    dir.join(naming::encode(relative, Some(mode)))
+                } else {
+                    dir.join(relative)
+                };
                 eprintln!(
                     "{}: {} -> {}",
                     "PULL".cyan(),
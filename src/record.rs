@@ -0,0 +1,113 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Record {
+    #[serde(default)]
+    hooks: BTreeMap<String, Entry>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Entry {
+    succeeded: String,
+    hash: Option<String>,
+}
+
+impl Record {
+    pub fn load(path: &Path) -> Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(buf) => toml::from_str(&buf)
+                .with_context(|| format!("invalid init record: {}", path.display())),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Whether the hook already succeeded with the same `onchange` hash.
+    pub fn is_done(&self, key: &str, hash: Option<&str>) -> bool {
+        self.hooks
+            .get(key)
+            .is_some_and(|entry| entry.hash.as_deref() == hash)
+    }
+
+    pub fn insert(&mut self, key: String, hash: Option<String>) -> Result<()> {
+        let succeeded = time::OffsetDateTime::now_utc()
+            .format(&time::format_description::well_known::Rfc3339)?;
+        self.hooks.insert(key, Entry { succeeded, hash });
+        Ok(())
+    }
+}
+
+/// Hashes the paths and contents of the files matching `patterns` under `dir`.
+pub fn hash_files(dir: &Path, patterns: &[String]) -> Result<String> {
+    let mut paths = vec![];
+    for pattern in patterns {
+        let pattern = dir.join(pattern);
+        for path in glob::glob(&pattern.to_string_lossy())?.flatten() {
+            if path.is_file() {
+                paths.push(path);
+            }
+        }
+    }
+    paths.sort();
+    paths.dedup();
+    let mut hasher = Sha256::new();
+    for path in paths {
+        let relative: PathBuf = path.strip_prefix(dir).unwrap_or(&path).to_path_buf();
+        hasher.update(relative.to_string_lossy().as_bytes());
+        hasher.update([0]);
+        hasher.update(fs::read(&path)?);
+        hasher.update([0]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_round_trips_and_compares_hashes() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("wagon-record-{}", std::process::id()));
+        let path = dir.join("init.toml");
+        let mut record = Record::load(&path)?;
+        assert!(!record.is_done("hook", None));
+        record.insert("hook".to_owned(), None)?;
+        record.insert("watched".to_owned(), Some("abc".to_owned()))?;
+        record.save(&path)?;
+
+        let record = Record::load(&path)?;
+        let _ = fs::remove_dir_all(&dir);
+        assert!(record.is_done("hook", None));
+        assert!(record.is_done("watched", Some("abc")));
+        assert!(!record.is_done("watched", Some("def")));
+        Ok(())
+    }
+
+    #[test]
+    fn hash_changes_with_file_contents() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("wagon-hash-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join("Brewfile"), "brew \"direnv\"\n")?;
+        let patterns = ["Brew*".to_owned()];
+        let before = hash_files(&dir, &patterns)?;
+        assert_eq!(before, hash_files(&dir, &patterns)?);
+        fs::write(dir.join("Brewfile"), "brew \"direnv\"\nbrew \"jq\"\n")?;
+        let after = hash_files(&dir, &patterns)?;
+        let _ = fs::remove_dir_all(&dir);
+        assert_ne!(before, after);
+        Ok(())
+    }
+}
@@ -0,0 +1,266 @@
+use crate::show::{Entry, Status};
+use crate::structs::{display_path, sanitize_display};
+use colored::Colorize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+const ELLIPSIS: char = '…';
+/// Narrowest the target and source columns of a table are shrunk to.
+const MIN_PATH_WIDTH: usize = 12;
+
+/// How `list` lays out the links.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// One `STATUS: target -> source` line per link, with diffs.
+    #[default]
+    Flat,
+    /// Targets grouped by destination directory.
+    Tree,
+    /// Aligned columns of status, target, source, size and mtime.
+    Table,
+}
+
+/// Columns of the terminal `list` writes to, from `COLUMNS` or the tty on
+/// stderr, `None` when the output is not a terminal.
+pub fn terminal_width() -> Option<usize> {
+    if let Some(columns) = env::var("COLUMNS").ok().and_then(|c| c.parse().ok()) {
+        return Some(columns).filter(|&c| c > 0);
+    }
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    let ok = unsafe { libc::ioctl(libc::STDERR_FILENO, libc::TIOCGWINSZ, &mut size) } == 0;
+    (ok && size.ws_col > 0).then_some(size.ws_col as usize)
+}
+
+/// Shortens `text` to `width` characters, keeping its end, which holds the
+/// file name, behind an ellipsis.
+fn fit(text: &str, width: usize) -> String {
+    let len = text.chars().count();
+    if len <= width {
+        return text.to_owned();
+    }
+    if width == 0 {
+        return String::new();
+    }
+    let tail = text.chars().skip(len - (width - 1));
+    std::iter::once(ELLIPSIS).chain(tail).collect()
+}
+
+fn pad(text: &str, width: usize) -> String {
+    let len = text.chars().count();
+    format!("{text}{}", " ".repeat(width.saturating_sub(len)))
+}
+
+/// The status label padded to `width`, padding outside the color codes.
+fn padded_label(status: Status, width: usize) -> String {
+    let label = status.label();
+    let padding = " ".repeat(width.saturating_sub(label.len()));
+    format!("{label}{padding}")
+}
+
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["K", "M", "G", "T"];
+    if bytes < 1024 {
+        return format!("{bytes}B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1}{}", UNITS[unit])
+}
+
+fn mtime(meta: &fs::Metadata) -> Option<String> {
+    let format =
+        time::format_description::parse_borrowed::<2>("[year]-[month]-[day] [hour]:[minute]")
+            .ok()?;
+    let mut date = time::OffsetDateTime::from(meta.modified().ok()?);
+    if let Ok(offset) = time::UtcOffset::current_local_offset() {
+        date = date.to_offset(offset);
+    }
+    date.format(&format).ok()
+}
+
+/// Deepest directory holding every target.
+fn common_dir(entries: &[Entry]) -> PathBuf {
+    let mut dirs = entries
+        .iter()
+        .map(|e| e.link.target.parent().unwrap_or(Path::new("/")));
+    let Some(first) = dirs.next() else {
+        return PathBuf::new();
+    };
+    dirs.fold(first.to_path_buf(), |common, dir| {
+        common
+            .components()
+            .zip(dir.components())
+            .take_while(|(a, b)| a == b)
+            .map(|(a, _)| a)
+            .collect()
+    })
+}
+
+#[derive(Default)]
+struct Node<'a> {
+    children: BTreeMap<String, Node<'a>>,
+    entry: Option<&'a Entry>,
+}
+
+impl<'a> Node<'a> {
+    fn insert(&mut self, rel: &Path, entry: &'a Entry) {
+        let node = rel
+            .components()
+            .filter(|c| matches!(c, Component::Normal(_)))
+            .fold(self, |node, name| {
+                let name = sanitize_display(&name.as_os_str().to_string_lossy());
+                node.children.entry(name).or_default()
+            });
+        node.entry = Some(entry);
+    }
+
+    fn render(&self, prefix: &str, width: Option<usize>, out: &mut Vec<String>) {
+        let mut children = self.children.iter().peekable();
+        while let Some((name, child)) = children.next() {
+            let last = children.peek().is_none();
+            let (branch, indent) = if last {
+                ("└── ", "    ")
+            } else {
+                ("├── ", "│   ")
+            };
+            let mut line = format!("{prefix}{branch}{name}");
+            if let Some(entry) = child.entry {
+                line = tree_line(&line, entry, width);
+            }
+            out.push(line);
+            child.render(&format!("{prefix}{indent}"), width, out);
+        }
+    }
+}
+
+/// A tree line with the status and source of the link after the name, the
+/// source shortened or dropped to fit `width`.
+fn tree_line(name: &str, entry: &Entry, width: Option<usize>) -> String {
+    let label = entry.status.label();
+    let source = display_path(&entry.link.source);
+    let used = name.chars().count() + 2 + label.len();
+    let room = width.map_or(usize::MAX, |w| w.saturating_sub(used + 4));
+    if room < MIN_PATH_WIDTH.min(source.chars().count()) {
+        return format!("{name}  {label}");
+    }
+    format!("{name}  {label} -> {}", fit(&source, room))
+}
+
+/// Lines of a tree of the targets below their common directory.
+pub fn tree(entries: &[Entry], width: Option<usize>) -> Vec<String> {
+    let root = common_dir(entries);
+    let mut tree = Node::default();
+    for entry in entries {
+        let rel = entry
+            .link
+            .target
+            .strip_prefix(&root)
+            .unwrap_or(&entry.link.target);
+        tree.insert(rel, entry);
+    }
+    let mut out = vec![display_path(&root).bold().to_string()];
+    tree.render("", width, &mut out);
+    out
+}
+
+/// Widths of the target and source columns, shrinking the wider ones until
+/// both fit into `room`.
+fn path_widths(target: usize, source: usize, room: usize) -> (usize, usize) {
+    if target + source <= room {
+        return (target, source);
+    }
+    let room = room.max(2 * MIN_PATH_WIDTH);
+    let half = room / 2;
+    match (target <= half, source <= half) {
+        (true, _) => (target, room - target),
+        (_, true) => (room - source, source),
+        _ => (half, room - half),
+    }
+}
+
+/// Lines of an aligned table of the links with the size and modification
+/// time of their existing targets.
+pub fn table(entries: &[Entry], width: Option<usize>) -> Vec<String> {
+    let header = ["STATUS", "TARGET", "SOURCE", "SIZE", "MODIFIED"];
+    let rows = entries
+        .iter()
+        .map(|entry| {
+            let meta = entry.meta.as_ref();
+            let size = meta
+                .filter(|m| !m.is_dir())
+                .map_or("-".to_owned(), |m| human_size(m.len()));
+            let modified = meta.and_then(mtime).unwrap_or("-".to_owned());
+            (
+                entry.status,
+                display_path(&entry.link.target),
+                display_path(&entry.link.source),
+                size,
+                modified,
+            )
+        })
+        .collect::<Vec<_>>();
+    let widest = |column: fn(&(Status, String, String, String, String)) -> usize, title: &str| {
+        rows.iter().map(column).max().unwrap_or(0).max(title.len())
+    };
+    let status_w = widest(|r| r.0.label().len(), header[0]);
+    let target_w = widest(|r| r.1.chars().count(), header[1]);
+    let source_w = widest(|r| r.2.chars().count(), header[2]);
+    let size_w = widest(|r| r.3.len(), header[3]);
+    let fixed = status_w + size_w + header[4].len().max(16) + 8;
+    let (target_w, source_w) = match width {
+        Some(width) => path_widths(target_w, source_w, width.saturating_sub(fixed)),
+        None => (target_w, source_w),
+    };
+
+    let title = format!(
+        "{}  {}  {}  {:>size_w$}  {}",
+        pad(header[0], status_w),
+        pad(header[1], target_w),
+        pad(header[2], source_w),
+        header[3],
+        header[4],
+    );
+    let mut out = vec![title.trim_end().bold().to_string()];
+    for (status, target, source, size, modified) in rows {
+        out.push(format!(
+            "{}  {}  {}  {size:>size_w$}  {modified}",
+            padded_label(status, status_w),
+            pad(&fit(&target, target_w), target_w),
+            pad(&fit(&source, source_w), source_w),
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fit_keeps_the_end_of_long_paths() {
+        assert_eq!(fit("/home/me/.vimrc", 20), "/home/me/.vimrc");
+        assert_eq!(fit("/home/me/.vimrc", 8), "…/.vimrc");
+        assert_eq!(fit("/home/me/.vimrc", 0), "");
+    }
+
+    #[test]
+    fn sizes_are_human_readable() {
+        assert_eq!(human_size(512), "512B");
+        assert_eq!(human_size(1536), "1.5K");
+        assert_eq!(human_size(3 * 1024 * 1024), "3.0M");
+    }
+
+    #[test]
+    fn wide_path_columns_shrink_to_fit() {
+        assert_eq!(path_widths(10, 20, 40), (10, 20));
+        assert_eq!(path_widths(10, 50, 40), (10, 30));
+        assert_eq!(path_widths(50, 50, 40), (20, 20));
+        assert_eq!(path_widths(50, 50, 4), (12, 12));
+    }
+}
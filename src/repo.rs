@@ -1,41 +1,612 @@
-use crate::config::GlobalConfig;
-use std::path::PathBuf;
+use crate::CONFFILE_NAME;
+use crate::config::{CloneOptions, Config, GlobalConfig, Layout, Protocol, parse_repo_list};
+use crate::hook::Report;
+use crate::structs::{display_path, sanitize_display};
+use crate::{git, init, prompt};
+use anyhow::{Context, Result, bail};
+use colored::Colorize;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
 
-pub fn load_repo(path: &str) -> anyhow::Result<()> {
-    let (site, path) = if let Some(site_path) = path.strip_prefix("https://") {
-        let (site, path) = site_path.split_once('/').unwrap_or_default();
+const SHORTHANDS: [(&str, &str); 3] = [
+    ("gh", "github.com"),
+    ("gl", "gitlab.com"),
+    ("bb", "bitbucket.org"),
+];
+const DEFAULT_SITE: &str = "github.com";
+const LOCAL_SITE: &str = "localhost";
+
+/// A repository to clone and the place it goes under the src directory.
+#[derive(Debug, PartialEq, Eq)]
+pub struct RepoSpec {
+    pub url: String,
+    pub site: String,
+    pub path: String,
+}
+
+impl RepoSpec {
+    fn new(url: String, site: &str, path: &str) -> Result<Self> {
+        let path = path.trim_matches('/').trim_end_matches(".git");
+        let valid = Path::new(path)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)));
+        if site.is_empty() || path.is_empty() || !valid {
+            bail!("invalid repository: {url}");
+        }
+        Ok(Self {
+            url,
+            site: site.to_owned(),
+            path: path.to_owned(),
+        })
+    }
+
+    fn from_site(conf: &GlobalConfig, site: &str, path: &str) -> Result<Self> {
         let path = path.trim_end_matches(".git");
-        (site, path)
-    } else if path.contains(':') {
-        let (pat, path) = path.split_once(':').unwrap_or_default();
-        let site = match pat {
-            "gh" => "github.com",
-            "gl" => "gitlab.com",
-            "bb" => "bitbucket.org",
-            _ => panic!("Unknown site or protocol: {pat}"),
+        let url = match conf.protocol {
+            Protocol::Https => format!("https://{site}/{path}.git"),
+            Protocol::Ssh => format!("git@{site}:{path}.git"),
         };
-        (site, path)
-    } else {
-        ("github.com", path)
+        Self::new(url, site, path)
+    }
+
+    /// Local path of the repository under `src`.
+    pub fn local_path(&self, src: &Path) -> PathBuf {
+        src.join(&self.site).join(&self.path)
+    }
+}
+
+/// Host of an URL authority, without user info and port.
+pub(crate) fn host(authority: &str) -> &str {
+    let host = authority.rsplit_once('@').map_or(authority, |(_, h)| h);
+    host.split_once(':').map_or(host, |(h, _)| h)
+}
+
+fn shorthand_site<'a>(conf: &'a GlobalConfig, name: &str) -> Option<&'a str> {
+    conf.shorthands.get(name).map(String::as_str).or_else(|| {
+        SHORTHANDS
+            .iter()
+            .find(|(short, _)| *short == name)
+            .map(|(_, site)| *site)
+    })
+}
+
+/// Parses URLs (`https://`, `http://`, `ssh://`, `git://`, `file://`),
+/// scp-style `[user@]host:owner/repo`, shorthands like `gh:owner/repo` and
+/// plain `owner/repo` for GitHub.
+pub fn parse(pathlike: &str, conf: &GlobalConfig) -> Result<RepoSpec> {
+    if let Some((scheme, rest)) = pathlike.split_once("://") {
+        return match scheme {
+            "https" | "http" | "ssh" | "git" => {
+                let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+                RepoSpec::new(pathlike.to_owned(), host(authority), path)
+            }
+            "file" => {
+                let path = rest.trim_end_matches('/').trim_end_matches(".git");
+                let mut parts = path.rsplitn(3, '/');
+                let (Some(repo), Some(owner)) = (parts.next(), parts.next()) else {
+                    bail!("invalid repository: {pathlike}");
+                };
+                RepoSpec::new(pathlike.to_owned(), LOCAL_SITE, &format!("{owner}/{repo}"))
+            }
+            _ => bail!("unknown protocol: {scheme}"),
+        };
+    }
+    match pathlike.split_once(':') {
+        Some((prefix, path)) if !prefix.contains('/') => {
+            if let Some(site) = shorthand_site(conf, prefix) {
+                RepoSpec::from_site(conf, site, path)
+            } else if prefix.contains('@') || prefix.contains('.') {
+                RepoSpec::new(pathlike.to_owned(), host(prefix), path)
+            } else {
+                bail!("unknown shorthand: {prefix}")
+            }
+        }
+        _ => RepoSpec::from_site(conf, DEFAULT_SITE, pathlike),
+    }
+}
+
+fn src_dir(conf: &GlobalConfig) -> PathBuf {
+    let mut src = dirs::home_dir().unwrap_or_default();
+    src.push(&conf.src);
+    src
+}
+
+/// Clones `spec` into `path` with `options` and returns the directory of the
+/// working tree, if the layout has one. Removes what was cloned on failure.
+fn clone_repo(spec: &RepoSpec, path: &Path, options: &CloneOptions) -> Result<Option<PathBuf>> {
+    let result = clone_into(spec, path, options);
+    if result.is_err() && path.exists() {
+        let _ = fs::remove_dir_all(path);
+    }
+    result
+}
+
+fn clone_into(spec: &RepoSpec, path: &Path, options: &CloneOptions) -> Result<Option<PathBuf>> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let layout = options.layout.unwrap_or_default();
+    let target = match layout {
+        Layout::Worktree => path.join(".bare"),
+        Layout::Normal | Layout::Bare => path.to_path_buf(),
     };
+    let mut args = vec!["clone".to_owned(), "--quiet".to_owned()];
+    if let Some(depth) = options.depth {
+        args.push(format!("--depth={depth}"));
+    }
+    if let Some(branch) = &options.branch {
+        args.push(format!("--branch={branch}"));
+    }
+    let recurse = options.recurse_submodules.unwrap_or_default();
+    match layout {
+        Layout::Normal if recurse => args.push("--recurse-submodules".to_owned()),
+        Layout::Normal => {}
+        Layout::Bare | Layout::Worktree => args.push("--bare".to_owned()),
+    }
+    args.extend(["--".to_owned(), spec.url.clone()]);
+    args.push(target.to_string_lossy().into_owned());
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    git::run(Path::new("."), &args)?;
 
-    let src_base = GlobalConfig::new().src;
+    match layout {
+        Layout::Normal => Ok(Some(path.to_path_buf())),
+        Layout::Bare => Ok(None),
+        Layout::Worktree => {
+            fs::write(path.join(".git"), "gitdir: ./.bare\n")?;
+            let fetch = "+refs/heads/*:refs/remotes/origin/*";
+            git::run(path, &["config", "remote.origin.fetch", fetch])?;
+            let branch = match &options.branch {
+                Some(branch) => branch.clone(),
+                None => git::run(path, &["symbolic-ref", "--short", "HEAD"])?,
+            };
+            git::run(path, &["worktree", "add", "--quiet", &branch, &branch])?;
+            let worktree = path.join(&branch);
+            if recurse {
+                git::run(
+                    &worktree,
+                    &["submodule", "update", "--quiet", "--init", "--recursive"],
+                )?;
+            }
+            Ok(Some(worktree))
+        }
+    }
+}
 
-    let mut repo_path = dirs::home_dir().unwrap_or_default();
-    repo_path.push(src_base);
-    eprintln!("Loading repository to: {}", repo_path.display());
-    repo_path.push(PathBuf::from(site));
-    repo_path.push(PathBuf::from(path));
+/// Runs the global `post_clone` hooks in a fresh clone, then the init hooks
+/// of its `.wagon.toml` when `init` is set or the user agrees.
+fn after_clone(
+    conf: &GlobalConfig,
+    path: &Path,
+    checkout: Option<&Path>,
+    init: Option<bool>,
+) -> Result<()> {
+    let dir = checkout.unwrap_or(path);
+    if !conf.post_clone.is_empty() {
+        let hook_conf = Config {
+            path: dir.join(CONFFILE_NAME),
+            ..Config::default()
+        };
+        let mut report = Report::default().with_changed(&[dir.to_path_buf()]);
+        report
+            .run_hooks(&hook_conf, &conf.post_clone, dir)
+            .and_then(|_| report.finish())
+            .with_context(|| {
+                format!(
+                    "post-clone hook failed in {}, the clone was kept",
+                    display_path(dir)
+                )
+            })?;
+    }
+    let Some(checkout) = checkout else {
+        return Ok(());
+    };
+    if !checkout.join(CONFFILE_NAME).exists() {
+        return Ok(());
+    }
+    let question = format!("Run the init hooks of {}?", display_path(checkout));
+    let run = match init {
+        Some(init) => init,
+        None => prompt::confirm(&question)?,
+    };
+    if run {
+        init::run_inits(&[checkout.to_path_buf()], false).with_context(|| {
+            format!(
+                "init hooks failed in {}, the clone was kept",
+                display_path(checkout)
+            )
+        })?;
+    } else if init.is_none() {
+        eprintln!(
+            "{}: {} has init hooks, run `wagon --base {} init` to run them",
+            "HINT".cyan(),
+            display_path(checkout),
+            display_path(checkout)
+        );
+    }
+    Ok(())
+}
 
+fn load_repo(
+    conf: &GlobalConfig,
+    pathlike: &str,
+    options: &CloneOptions,
+    init: Option<bool>,
+) -> Result<()> {
+    let spec = parse(pathlike, conf)?;
+    let repo_path = spec.local_path(&src_dir(conf));
     if repo_path.exists() {
-        eprintln!("Repository already exists.");
+        eprintln!("{}: {}", "EXISTS".cyan(), display_path(&repo_path));
     } else {
-        let url = format!("https://{site}/{path}.git");
-        let output = std::process::Command::new("git")
-            .args(["clone", &url, repo_path.to_str().unwrap_or_default()])
-            .output()?;
-        eprintln!("{}: {}", url, String::from_utf8(output.stderr)?);
+        eprintln!(
+            "{}: {} into {}",
+            "CLONING".green(),
+            sanitize_display(&spec.url),
+            display_path(&repo_path)
+        );
+        let checkout = clone_repo(&spec, &repo_path, options)?;
+        after_clone(conf, &repo_path, checkout.as_deref(), init)?;
     }
     println!("{}", repo_path.display());
     Ok(())
 }
+
+/// Clones each of `pathlikes` unless it exists, failing when any clone fails.
+pub fn load_repos(pathlikes: &[String], options: CloneOptions, init: Option<bool>) -> Result<()> {
+    let conf = GlobalConfig::new()?;
+    let options = options.or(&conf.clone);
+    let mut failed = 0;
+    for pathlike in pathlikes {
+        if let Err(err) = load_repo(&conf, pathlike, &options, init) {
+            eprintln!(
+                "{}: {} ({})",
+                "FAILED".red(),
+                sanitize_display(pathlike),
+                sanitize_display(&format!("{err:#}"))
+            );
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        bail!(
+            "{failed} of {} repositories failed to clone or set up",
+            pathlikes.len()
+        );
+    }
+    Ok(())
+}
+
+/// Finds the git repositories below `dir`, without descending into them.
+fn find_repos(dir: &Path, repos: &mut Vec<PathBuf>) -> Result<()> {
+    let mut entries = fs::read_dir(dir)?.collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = entry.path();
+        if !entry.file_type()?.is_dir() || entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        if path.join(".git").exists()
+            || path.join("HEAD").is_file() && path.join("objects").is_dir()
+        {
+            repos.push(path);
+        } else {
+            find_repos(&path, repos)?;
+        }
+    }
+    Ok(())
+}
+
+fn cloned_repos(src: &Path) -> Result<Vec<PathBuf>> {
+    let mut repos = vec![];
+    if src.is_dir() {
+        find_repos(src, &mut repos).with_context(|| format!("cannot read {}", src.display()))?;
+    }
+    Ok(repos)
+}
+
+/// Prints the cloned repositories with their branch, working tree state and
+/// distance from the upstream.
+pub fn list_repos() -> Result<()> {
+    let src = src_dir(&GlobalConfig::new()?);
+    let rows = cloned_repos(&src)?
+        .into_iter()
+        .map(|path| {
+            let name = display_path(path.strip_prefix(&src).unwrap_or(&path));
+            let branch =
+                git::branch(&path).map_or_else(|_| "?".to_owned(), |b| sanitize_display(&b));
+            let state = match git::is_bare(&path) {
+                Ok(true) => "bare",
+                Ok(false) => match git::is_dirty(&path) {
+                    Ok(true) => "dirty",
+                    Ok(false) => "clean",
+                    Err(_) => "?",
+                },
+                Err(_) => "?",
+            };
+            let upstream = match git::ahead_behind(&path) {
+                Ok(Some((0, 0))) => "up to date".to_owned(),
+                Ok(Some((ahead, behind))) => format!("ahead {ahead}, behind {behind}"),
+                Ok(None) => "no upstream".to_owned(),
+                Err(_) => "?".to_owned(),
+            };
+            (name, branch, state, upstream)
+        })
+        .collect::<Vec<_>>();
+    let width = |f: fn(&(String, String, &str, String)) -> usize, title: &str| {
+        rows.iter().map(f).max().unwrap_or(0).max(title.len())
+    };
+    let name_width = width(|r| r.0.chars().count(), "REPO");
+    let branch_width = width(|r| r.1.chars().count(), "BRANCH");
+    println!(
+        "{}",
+        format!(
+            "{:name_width$}  {:branch_width$}  {:5}  UPSTREAM",
+            "REPO", "BRANCH", "STATE"
+        )
+        .bold()
+    );
+    for (name, branch, state, upstream) in rows {
+        let state = match state {
+            "clean" => format!("{state:5}").green(),
+            _ => format!("{state:5}").yellow(),
+        };
+        println!("{name:name_width$}  {branch:branch_width$}  {state}  {upstream}");
+    }
+    Ok(())
+}
+
+/// Ranks how well `pattern` matches a repository path relative to the src
+/// directory, lower is better: the repository name itself, then substrings of
+/// the name and the path, then a subsequence of the path.
+fn match_rank(pattern: &str, relative: &str) -> Option<u8> {
+    let pattern = pattern.to_lowercase();
+    let relative = relative.to_lowercase();
+    let name = relative.rsplit('/').next().unwrap_or(&relative);
+    if name == pattern || relative.ends_with(&format!("/{pattern}")) {
+        Some(0)
+    } else if name.contains(&pattern) {
+        Some(1)
+    } else if relative.contains(&pattern) {
+        Some(2)
+    } else {
+        let mut chars = relative.chars();
+        pattern.chars().all(|p| chars.any(|c| c == p)).then_some(3)
+    }
+}
+
+fn best_match<'a>(pattern: &str, relatives: &'a [String]) -> Option<&'a String> {
+    relatives
+        .iter()
+        .filter_map(|relative| Some((match_rank(pattern, relative)?, relative)))
+        .min_by_key(|(rank, relative)| (*rank, relative.len(), *relative))
+        .map(|(_, relative)| relative)
+}
+
+/// Prints the path of the cloned repository best matching `pattern`.
+pub fn print_repo_path(pattern: &str) -> Result<()> {
+    let src = src_dir(&GlobalConfig::new()?);
+    let repos = cloned_repos(&src)?;
+    let relatives = repos
+        .iter()
+        .map(|path| {
+            let relative = path.strip_prefix(&src).unwrap_or(path);
+            relative.to_string_lossy().into_owned()
+        })
+        .collect::<Vec<_>>();
+    let Some(relative) = best_match(pattern, &relatives) else {
+        bail!("no repository matches `{}`", sanitize_display(pattern));
+    };
+    println!("{}", src.join(relative).display());
+    Ok(())
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum SyncStatus {
+    Cloned,
+    Fetched,
+    Updated(usize),
+    UpToDate,
+    Ahead(usize),
+    NoUpstream,
+    Dirty,
+    Diverged(usize, usize),
+    Failed(String),
+}
+
+impl SyncStatus {
+    fn line(&self, name: &str) -> String {
+        match self {
+            SyncStatus::Cloned => format!("{}: {name}", "CLONED".green()),
+            SyncStatus::Fetched => format!("{}: {name}", "FETCHED".green()),
+            SyncStatus::Updated(n) => format!("{}: {name} ({n} new commits)", "UPDATED".green()),
+            SyncStatus::UpToDate => format!("{}: {name}", "UP TO DATE".cyan()),
+            SyncStatus::Ahead(n) => format!("{}: {name} ({n} commits ahead)", "AHEAD".cyan()),
+            SyncStatus::NoUpstream => format!("{}: {name}", "NO UPSTREAM".yellow()),
+            SyncStatus::Dirty => format!("{}: {name} (not touched)", "DIRTY".yellow()),
+            SyncStatus::Diverged(ahead, behind) => format!(
+                "{}: {name} ({ahead} ahead, {behind} behind, not touched)",
+                "DIVERGED".yellow()
+            ),
+            SyncStatus::Failed(err) => {
+                format!("{}: {name} ({})", "FAILED".red(), sanitize_display(err))
+            }
+        }
+    }
+}
+
+fn sync_repo(spec: &RepoSpec, path: &Path, options: &CloneOptions) -> Result<SyncStatus> {
+    if !path.exists() {
+        clone_repo(spec, path, options)?;
+        return Ok(SyncStatus::Cloned);
+    }
+    if git::is_bare(path)? {
+        git::run(path, &["fetch", "--quiet"])?;
+        return Ok(SyncStatus::Fetched);
+    }
+    if git::is_dirty(path)? {
+        return Ok(SyncStatus::Dirty);
+    }
+    git::run(path, &["fetch", "--quiet"])?;
+    Ok(match git::ahead_behind(path)? {
+        None => SyncStatus::NoUpstream,
+        Some((0, 0)) => SyncStatus::UpToDate,
+        Some((ahead, 0)) => SyncStatus::Ahead(ahead),
+        Some((0, behind)) => {
+            git::run(path, &["merge", "--quiet", "--ff-only", "@{u}"])?;
+            SyncStatus::Updated(behind)
+        }
+        Some((ahead, behind)) => SyncStatus::Diverged(ahead, behind),
+    })
+}
+
+/// Clones the missing repositories of the manifest and fast-forwards the
+/// existing ones, `jobs` at a time.
+pub fn sync_repos(file: Option<&Path>, jobs: usize) -> Result<()> {
+    let conf = GlobalConfig::new()?;
+    let repos = match file {
+        Some(file) => parse_repo_list(
+            &fs::read_to_string(file).with_context(|| format!("cannot read {}", file.display()))?,
+        ),
+        None => conf.repo_list()?,
+    };
+    let specs = repos
+        .iter()
+        .map(|repo| parse(repo, &conf))
+        .collect::<Result<Vec<_>>>()?;
+    let src = src_dir(&conf);
+    let next = AtomicUsize::new(0);
+    let failed = Mutex::new(0);
+    thread::scope(|scope| {
+        for _ in 0..jobs.max(1) {
+            scope.spawn(|| {
+                while let Some(spec) = specs.get(next.fetch_add(1, Ordering::SeqCst)) {
+                    let path = spec.local_path(&src);
+                    let status = sync_repo(spec, &path, &conf.clone)
+                        .unwrap_or_else(|err| SyncStatus::Failed(format!("{err:#}")));
+                    if matches!(status, SyncStatus::Failed(_)) {
+                        *failed.lock().unwrap() += 1;
+                    }
+                    eprintln!("{}", status.line(&display_path(&path)));
+                }
+            });
+        }
+    });
+    let failed = failed.into_inner().unwrap();
+    if failed > 0 {
+        bail!("{failed} of {} repositories failed to sync", specs.len());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(url: &str, site: &str, path: &str) -> RepoSpec {
+        RepoSpec {
+            url: url.to_owned(),
+            site: site.to_owned(),
+            path: path.to_owned(),
+        }
+    }
+
+    #[test]
+    fn path_lookup_prefers_repository_names() {
+        let repos = [
+            "github.com/yasuyuky/wagon-tools".to_owned(),
+            "github.com/yasuyuky/wagon".to_owned(),
+            "github.com/wagon-rs/core".to_owned(),
+            "gitlab.com/someone/dotfiles".to_owned(),
+        ];
+        assert_eq!(best_match("wagon", &repos), Some(&repos[1]));
+        assert_eq!(best_match("Tools", &repos), Some(&repos[0]));
+        assert_eq!(best_match("wagon-rs", &repos), Some(&repos[2]));
+        assert_eq!(best_match("smdot", &repos), Some(&repos[3]));
+        assert_eq!(best_match("nothing", &repos), None);
+    }
+
+    #[test]
+    fn parses_urls() -> Result<()> {
+        let conf = GlobalConfig::default();
+        for (url, site, path) in [
+            (
+                "https://github.com/yasuyuky/wagon.git",
+                "github.com",
+                "yasuyuky/wagon",
+            ),
+            (
+                "https://gitlab.example.com:8443/group/sub/project",
+                "gitlab.example.com",
+                "group/sub/project",
+            ),
+            (
+                "ssh://git@gitlab.example.com:2222/group/project.git",
+                "gitlab.example.com",
+                "group/project",
+            ),
+            ("git://example.org/owner/repo", "example.org", "owner/repo"),
+            (
+                "git@github.com:yasuyuky/wagon.git",
+                "github.com",
+                "yasuyuky/wagon",
+            ),
+            (
+                "gitlab.example.com:group/project",
+                "gitlab.example.com",
+                "group/project",
+            ),
+            ("file:///srv/git/owner/repo.git", "localhost", "owner/repo"),
+        ] {
+            assert_eq!(parse(url, &conf)?, spec(url, site, path), "{url}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn parses_shorthands_with_protocol() -> Result<()> {
+        let mut conf = GlobalConfig::default();
+        assert_eq!(
+            parse("yasuyuky/wagon", &conf)?,
+            spec(
+                "https://github.com/yasuyuky/wagon.git",
+                "github.com",
+                "yasuyuky/wagon"
+            )
+        );
+        conf.protocol = Protocol::Ssh;
+        conf.shorthands
+            .insert("work".to_owned(), "git.example.com".to_owned());
+        assert_eq!(
+            parse("gl:group/project", &conf)?,
+            spec(
+                "git@gitlab.com:group/project.git",
+                "gitlab.com",
+                "group/project"
+            )
+        );
+        assert_eq!(
+            parse("work:team/repo", &conf)?,
+            spec(
+                "git@git.example.com:team/repo.git",
+                "git.example.com",
+                "team/repo"
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_unknown_and_unsafe_repositories() {
+        let conf = GlobalConfig::default();
+        for pathlike in [
+            "xx:owner/repo",
+            "ftp://host/owner/repo",
+            "https://github.com/../etc",
+            "gh:",
+        ] {
+            assert!(parse(pathlike, &conf).is_err(), "{pathlike}");
+        }
+    }
+}
@@ -1,37 +1,86 @@
 use crate::{
-    Content, Link,
+    Link,
+    compare::{Comparison, DEFAULT_MAX_DIFF_SIZE, compare, compare_dirs},
+    config::GlobalConfig,
+    diff::{HEX_DIFF_LIMIT, hex_dump, structured_diff},
     list::list_items,
+    render::{self, Format},
     structs::{display_path, sanitize_display},
 };
-use anyhow::Result;
-use colored::Colorize;
+use anyhow::{Context, Result, bail};
+use colored::{ColoredString, Colorize};
+use glob::Pattern;
 use std::fs;
-use std::io::{self, Read};
+use std::io;
 use std::path::{Path, PathBuf};
+use std::process;
 
-fn read_text(f: &mut fs::File) -> Result<Content> {
-    let mut buf = String::default();
-    f.read_to_string(&mut buf)?;
-    let ss = buf.lines().map(String::from).collect();
-    Ok(Content::Text(ss))
+const DEFAULT_CONTEXT: usize = 3;
+
+/// How `list` shows the differences between sources and existing targets.
+#[derive(Debug, Default)]
+pub struct ListOptions {
+    /// Lines of context of the unified diff, from the global config if unset.
+    pub context: Option<usize>,
+    /// External diff command, from the global config if unset.
+    pub diff_tool: Option<String>,
+    /// List the links without any diff.
+    pub no_diff: bool,
+    /// Show the number of added and removed lines instead of the diff.
+    pub stat: bool,
+    /// Largest file loaded for a diff, from the global config.
+    pub max_diff_size: Option<u64>,
+    /// Only show links with these statuses, all when empty.
+    pub only: Vec<Status>,
+    /// Hide links with these statuses.
+    pub exclude_status: Vec<Status>,
+    /// Only show links whose source, relative to the directory, or target
+    /// matches one of these globs, all when empty.
+    pub paths: Vec<String>,
+    /// Print the number of links per status instead of the links.
+    pub summary: bool,
+    /// Layout of the links; only the flat one shows diffs.
+    pub format: Format,
+}
+
+fn max_size(options: &ListOptions) -> u64 {
+    options.max_diff_size.unwrap_or(DEFAULT_MAX_DIFF_SIZE)
 }
 
-fn read_binary(f: &mut fs::File) -> Result<Content> {
-    let mut buf = Vec::new();
-    let size = f.read_to_end(&mut buf)?;
-    Ok(Content::Binary(size, buf))
+impl ListOptions {
+    fn or(self, conf: &GlobalConfig) -> ListOptions {
+        ListOptions {
+            context: self.context.or(conf.diff_context),
+            diff_tool: self.diff_tool.or_else(|| conf.diff_tool.clone()),
+            max_diff_size: self.max_diff_size.or(conf.max_diff_size),
+            ..self
+        }
+    }
 }
 
-fn read_content(path: &Path) -> Result<(Content, String, String)> {
-    let mut f = fs::File::open(path)?;
-    let meta = f.metadata()?;
-    let date = format!("{}", time::OffsetDateTime::from(meta.modified()?));
-    let ps = path.to_str().unwrap_or_default().to_owned();
-    Ok((read_text(&mut f).unwrap_or(read_binary(&mut f)?), ps, date))
+/// Lines added and removed over the existing targets of a directory.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Stat {
+    files: usize,
+    added: usize,
+    removed: usize,
 }
 
-fn get_text_diff(ss: &[String], ts: &[String], sp: &str, tp: &str, sd: &str, td: &str) -> String {
-    difflib::unified_diff(ss, ts, sp, tp, sd, td, 3)
+fn modified(path: &Path) -> Result<String> {
+    let meta = fs::metadata(path)?;
+    Ok(format!("{}", time::OffsetDateTime::from(meta.modified()?)))
+}
+
+fn get_text_diff(
+    ss: &[String],
+    ts: &[String],
+    sp: &str,
+    tp: &str,
+    sd: &str,
+    td: &str,
+    context: usize,
+) -> String {
+    difflib::unified_diff(ss, ts, sp, tp, sd, td, context)
         .iter()
         .map(|line| {
             let line = sanitize_display(line.trim_end());
@@ -47,26 +96,119 @@ fn get_text_diff(ss: &[String], ts: &[String], sp: &str, tp: &str, sd: &str, td:
         .join("\n")
 }
 
-fn check_binary_diff(ssz: usize, sb: Vec<u8>, tsz: usize, tb: Vec<u8>) -> String {
-    if sb != tb {
-        format!(
-            "{} src size:{}, dst size:{}",
-            "binary files do not match.".red(),
-            ssz,
-            tsz
-        )
-    } else {
-        String::default()
+fn check_binary_diff(ssz: u64, tsz: u64) -> String {
+    format!(
+        "{} src size:{}, dst size:{}",
+        "binary files do not match.".red(),
+        ssz,
+        tsz
+    )
+}
+
+fn too_large(ssz: u64, tsz: u64) -> String {
+    format!(
+        "{} src size:{}, dst size:{}",
+        "files too large to diff.".yellow(),
+        ssz,
+        tsz
+    )
+}
+
+/// Counts the lines only in the target as added and those only in the
+/// source as removed, as the unified diff shows them.
+fn count_changes(ss: &[String], ts: &[String]) -> (usize, usize) {
+    let (mut added, mut removed) = (0, 0);
+    for line in difflib::unified_diff(ss, ts, "", "", "", "", 0)
+        .iter()
+        .skip(2)
+    {
+        if line.starts_with('+') {
+            added += 1;
+        } else if line.starts_with('-') {
+            removed += 1;
+        }
     }
+    (added, removed)
 }
 
-fn show_content_diff(link: &Link) -> Result<String> {
-    let (srcc, sp, srcd) = read_content(&link.source)?;
-    let (tgtc, tp, tgtd) = read_content(&link.target)?;
-    Ok(match (srcc, tgtc) {
-        (Content::Text(ss), Content::Text(ts)) => get_text_diff(&ss, &ts, &sp, &tp, &srcd, &tgtd),
-        (Content::Binary(ssz, sb), Content::Binary(tsz, tb)) => check_binary_diff(ssz, sb, tsz, tb),
-        _ => "file types do not match".to_owned(),
+fn show_stat(link: &Link, max_size: u64, stat: &mut Stat) -> Result<String> {
+    let (added, removed) = match compare(&link.source, &link.target, max_size)? {
+        Comparison::Same => (0, 0),
+        Comparison::Text(ss, ts) => count_changes(&ss, &ts),
+        Comparison::TooLarge(..) => {
+            return Ok(format!("    {}", "files too large to diff".yellow()));
+        }
+        Comparison::Binary(..) | Comparison::TypeMismatch => {
+            return Ok(format!("    {}", "binary files differ".red()));
+        }
+    };
+    if added + removed > 0 {
+        stat.files += 1;
+        stat.added += added;
+        stat.removed += removed;
+    }
+    Ok(format!(
+        "    {} {}",
+        format!("+{added}").green(),
+        format!("-{removed}").red()
+    ))
+}
+
+/// Runs the external diff tool with the source and target, replacing the
+/// `{source}` and `{target}` placeholders or appending both paths.
+fn run_diff_tool(tool: &str, link: &Link, context: usize) -> Result<()> {
+    let source = link.source.to_string_lossy();
+    let target = link.target.to_string_lossy();
+    let mut args = tool
+        .split_whitespace()
+        .map(|word| {
+            word.replace("{source}", &source)
+                .replace("{target}", &target)
+                .replace("{context}", &context.to_string())
+        })
+        .collect::<Vec<_>>();
+    if !tool.contains("{source}") && !tool.contains("{target}") {
+        args.extend([source.into_owned(), target.into_owned()]);
+    }
+    let Some((program, args)) = args.split_first() else {
+        bail!("empty diff tool");
+    };
+    // Diff tools exit non-zero when the files differ, so only a failure to
+    // start is an error.
+    process::Command::new(program)
+        .args(args)
+        .status()
+        .with_context(|| format!("cannot run diff tool {}", sanitize_display(program)))?;
+    Ok(())
+}
+
+fn unified_diff(link: &Link, ss: &[String], ts: &[String], context: usize) -> Result<String> {
+    let (sp, tp) = (link.source.to_string_lossy(), link.target.to_string_lossy());
+    let (sd, td) = (modified(&link.source)?, modified(&link.target)?);
+    Ok(get_text_diff(ss, ts, &sp, &tp, &sd, &td, context))
+}
+
+fn show_content_diff(link: &Link, context: usize, max_size: u64) -> Result<String> {
+    Ok(match compare(&link.source, &link.target, max_size)? {
+        Comparison::Same => String::default(),
+        Comparison::Text(ss, ts) => {
+            match structured_diff(&link.target, &ss.join("\n"), &ts.join("\n")) {
+                Some(diff) => diff,
+                None => unified_diff(link, &ss, &ts, context)?,
+            }
+        }
+        Comparison::Binary(ssz, tsz) if ssz.max(tsz) <= HEX_DIFF_LIMIT => {
+            let ss = hex_dump(&fs::read(&link.source)?);
+            let ts = hex_dump(&fs::read(&link.target)?);
+            format!(
+                "{}\n{}",
+                check_binary_diff(ssz, tsz),
+                unified_diff(link, &ss, &ts, context)?
+            )
+        }
+        Comparison::Binary(ssz, tsz) => check_binary_diff(ssz, tsz),
+        Comparison::TooLarge(ssz, tsz) => too_large(ssz, tsz),
+        Comparison::TypeMismatch => "file types do not match".to_owned(),
     })
 }
 
@@ -78,56 +220,203 @@ fn target_metadata(path: &Path) -> Result<Option<fs::Metadata>> {
     }
 }
 
-fn show_existing_target(link: &Link, target_meta: &fs::Metadata) -> Result<Vec<String>> {
-    let mut lines = vec![format!(
-        "{}: {}",
-        "EXISTS".magenta(),
-        display_path(&link.target)
-    )];
-    if !link.is_dir && target_meta.is_file() {
-        lines.push(show_content_diff(link)?)
+fn show_file_diff(link: &Link, options: &ListOptions, stat: &mut Stat) -> Result<()> {
+    if options.no_diff {
+        return Ok(());
+    }
+    let context = options.context.unwrap_or(DEFAULT_CONTEXT);
+    if options.stat {
+        eprintln!("{}", show_stat(link, max_size(options), stat)?);
+    } else if let Some(tool) = &options.diff_tool {
+        run_diff_tool(tool, link, context)?;
+    } else {
+        eprintln!("{}", show_content_diff(link, context, max_size(options))?);
     }
-    Ok(lines)
+    Ok(())
 }
 
-fn show_link(base: &Path) -> Result<String> {
-    let mut vs = vec![];
-    for link in list_items(base, false)? {
-        let target_meta = match target_metadata(&link.target) {
-            Ok(meta) => meta,
-            Err(err) => {
-                vs.push(format!(
-                    "{} cannot access: {} ({err})",
-                    "ERROR:".red(),
-                    display_path(&link.target)
-                ));
-                continue;
-            }
-        };
-        if let Ok(readlink) = fs::read_link(&link.target) {
-            if readlink == link.source {
-                vs.push(format!("{}: {}", "LINKING".cyan(), &link))
-            } else if let Some(meta) = target_meta.as_ref() {
-                vs.extend(show_existing_target(&link, meta)?)
-            } else {
-                vs.push(format!(
-                    "{} broken symlink: {} -> {}",
-                    "ERROR:".red(),
-                    display_path(&link.target),
-                    display_path(&readlink)
-                ));
-                vs.push(format!("{}: {}", "NOLINK".yellow(), &link))
-            }
-        } else if let Some(meta) = target_meta.as_ref() {
-            vs.extend(show_existing_target(&link, meta)?)
-        } else {
-            vs.push(format!("{}: {}", "NOLINK".yellow(), &link))
+/// Lists the entries only in the repo or only in the target directory and
+/// shows the diffs of the files in both that differ.
+fn show_dir_diff(link: &Link, options: &ListOptions, stat: &mut Stat) -> Result<()> {
+    let comparison = match compare_dirs(&link.source, &link.target) {
+        Ok(comparison) => comparison,
+        Err(err) => {
+            eprintln!(
+                "{} cannot compare: {link} ({})",
+                "ERROR:".red(),
+                sanitize_display(&err.to_string())
+            );
+            return Ok(());
+        }
+    };
+    for path in &comparison.only_source {
+        let path = link.source.join(path);
+        eprintln!("{}: {}", "ONLY IN REPO".green(), display_path(&path));
+    }
+    for path in &comparison.only_target {
+        let path = link.target.join(path);
+        eprintln!("{}: {}", "ONLY IN TARGET".yellow(), display_path(&path));
+    }
+    for path in &comparison.differing {
+        let file = Link::new(link.source.join(path), link.target.join(path), false);
+        eprintln!("{}: {file}", "DIFFERS".magenta());
+        if file.source.is_file() && file.target.is_file() {
+            show_file_diff(&file, options, stat)?;
         }
     }
-    Ok(vs.join("\n"))
+    Ok(())
+}
+
+fn show_existing_target(
+    link: &Link,
+    target_meta: &fs::Metadata,
+    options: &ListOptions,
+    stat: &mut Stat,
+) -> Result<()> {
+    eprintln!("{}: {}", "EXISTS".magenta(), display_path(&link.target));
+    match (link.is_dir, target_meta.is_dir()) {
+        (true, true) => show_dir_diff(link, options, stat),
+        (false, false) if target_meta.is_file() => show_file_diff(link, options, stat),
+        _ => Ok(()),
+    }
 }
 
-pub fn show_list(dirs: &[PathBuf]) -> Result<()> {
+/// State of a link's target, as shown by `list`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum)]
+pub enum Status {
+    /// The target links to the source.
+    Linking,
+    /// The target does not exist yet.
+    Nolink,
+    /// Something else exists at the target.
+    Exists,
+    /// The target is a symlink to a missing file.
+    Broken,
+    /// The target cannot be accessed.
+    Error,
+}
+
+impl Status {
+    const ALL: [Status; 5] = [
+        Status::Linking,
+        Status::Nolink,
+        Status::Exists,
+        Status::Broken,
+        Status::Error,
+    ];
+
+    pub(crate) fn label(self) -> ColoredString {
+        match self {
+            Status::Linking => "LINKING".cyan(),
+            Status::Nolink => "NOLINK".yellow(),
+            Status::Exists => "EXISTS".magenta(),
+            Status::Broken => "BROKEN".red(),
+            Status::Error => "ERROR".red(),
+        }
+    }
+}
+
+/// A link with the state of its target.
+pub(crate) struct Entry {
+    pub(crate) link: Link,
+    pub(crate) status: Status,
+    pub(crate) meta: Option<fs::Metadata>,
+    /// Where a broken symlink points, or why the target cannot be accessed.
+    detail: String,
+}
+
+pub(crate) fn classify(link: Link) -> Entry {
+    let (status, meta, detail) = match target_metadata(&link.target) {
+        Err(err) => (Status::Error, None, err.to_string()),
+        Ok(meta) => match fs::read_link(&link.target) {
+            Ok(readlink) if readlink == link.source => (Status::Linking, meta, String::new()),
+            Ok(readlink) if meta.is_none() => (Status::Broken, None, display_path(&readlink)),
+            _ if meta.is_some() => (Status::Exists, meta, String::new()),
+            _ => (Status::Nolink, None, String::new()),
+        },
+    };
+    Entry {
+        link,
+        status,
+        meta,
+        detail,
+    }
+}
+
+impl ListOptions {
+    fn patterns(&self) -> Result<Vec<Pattern>> {
+        self.paths
+            .iter()
+            .map(|path| {
+                Pattern::new(path)
+                    .with_context(|| format!("invalid path pattern `{}`", sanitize_display(path)))
+            })
+            .collect()
+    }
+
+    fn selects(&self, patterns: &[Pattern], base: &Path, entry: &Entry) -> bool {
+        let link = &entry.link;
+        let source = link.source.strip_prefix(base).unwrap_or(&link.source);
+        (self.only.is_empty() || self.only.contains(&entry.status))
+            && !self.exclude_status.contains(&entry.status)
+            && (patterns.is_empty()
+                || patterns
+                    .iter()
+                    .any(|p| p.matches_path(source) || p.matches_path(&link.target)))
+    }
+}
+
+fn show_entry(entry: &Entry, options: &ListOptions, stat: &mut Stat) -> Result<()> {
+    let link = &entry.link;
+    match (entry.status, &entry.meta) {
+        (Status::Exists, Some(meta)) => show_existing_target(link, meta, options, stat)?,
+        (Status::Broken, _) => {
+            eprintln!(
+                "{} broken symlink: {} -> {}",
+                "ERROR:".red(),
+                display_path(&link.target),
+                entry.detail
+            );
+            eprintln!("{}: {}", Status::Broken.label(), link)
+        }
+        (Status::Error, _) => eprintln!(
+            "{} cannot access: {} ({})",
+            "ERROR:".red(),
+            display_path(&link.target),
+            sanitize_display(&entry.detail)
+        ),
+        (status, _) => eprintln!("{}: {}", status.label(), link),
+    }
+    Ok(())
+}
+
+fn list_entries(base: &Path, options: &ListOptions) -> Result<Vec<Entry>> {
+    let patterns = options.patterns()?;
+    let canonical = base.canonicalize()?;
+    Ok(list_items(base, false)?
+        .into_iter()
+        .map(classify)
+        .filter(|entry| options.selects(&patterns, &canonical, entry))
+        .collect())
+}
+
+fn show_summary(entries: &[Entry]) {
+    let counts = Status::ALL
+        .iter()
+        .map(|status| {
+            (
+                status,
+                entries.iter().filter(|e| e.status == *status).count(),
+            )
+        })
+        .filter(|(_, count)| *count > 0)
+        .map(|(status, count)| format!("{}: {count}", status.label()))
+        .collect::<Vec<_>>();
+    eprintln!("{}", counts.join(", "));
+}
+
+pub fn show_list(dirs: &[PathBuf], options: ListOptions) -> Result<()> {
+    let options = options.or(&GlobalConfig::new()?);
     for dir in dirs {
         if fs::metadata(dir)?.is_dir() {
             if let Some(name) = dir.file_name() {
@@ -135,8 +424,60 @@ pub fn show_list(dirs: &[PathBuf]) -> Result<()> {
                 // without disabling tracing sanitization globally.
                 eprintln!("{}", sanitize_display(&name.to_string_lossy()).bold());
             }
-            eprintln!("{}", show_link(dir)?)
+            let entries = list_entries(dir, &options)?;
+            if options.summary {
+                show_summary(&entries);
+                continue;
+            }
+            let width = render::terminal_width();
+            let lines = match options.format {
+                Format::Flat => vec![],
+                Format::Tree => render::tree(&entries, width),
+                Format::Table => render::table(&entries, width),
+            };
+            if options.format != Format::Flat {
+                for line in lines {
+                    eprintln!("{line}");
+                }
+                continue;
+            }
+            let mut stat = Stat::default();
+            for entry in &entries {
+                show_entry(entry, &options, &mut stat)?;
+            }
+            if options.stat {
+                eprintln!(
+                    "{} files changed, {} insertions(+), {} deletions(-)",
+                    stat.files, stat.added, stat.removed
+                );
+            }
         }
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn counts_added_and_removed_lines() {
+        let source = lines("a\nb\nc\nd\n");
+        let target = lines("a\nB\nc\nd\ne\n");
+        assert_eq!(count_changes(&source, &target), (2, 1));
+        assert_eq!(count_changes(&source, &source), (0, 0));
+    }
+
+    #[test]
+    fn context_limits_unchanged_lines() {
+        let source = lines("1\n2\n3\n4\n5\n6\n7\n");
+        let target = lines("1\n2\n3\nfour\n5\n6\n7\n");
+        let diff = |context| get_text_diff(&source, &target, "s", "t", "", "", context);
+        assert!(!diff(0).contains("\n 3"));
+        assert!(diff(1).contains("\n 3") && !diff(1).contains("\n 2"));
+    }
+}
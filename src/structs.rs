@@ -35,6 +35,7 @@ pub struct Link {
     pub source: PathBuf,
     pub target: PathBuf,
     pub is_dir: bool,
+    pub mode: Option<u32>,
 }
 
 impl Link {
@@ -43,8 +44,14 @@ impl Link {
             source,
             target,
             is_dir,
+            mode: None,
         }
     }
+
+    pub fn with_mode(mut self, mode: Option<u32>) -> Self {
+        self.mode = mode;
+        self
+    }
 }
 
 impl std::fmt::Display for Link {
@@ -58,11 +65,6 @@ impl std::fmt::Display for Link {
     }
 }
 
-pub enum Content {
-    Text(Vec<String>),
-    Binary(usize, Vec<u8>),
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
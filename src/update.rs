@@ -1,42 +1,125 @@
-use crate::{config::get_config, structs::sanitize_output};
+use crate::{config::Config, hook::Report, order::ordered_configs};
 use anyhow::Result;
-use std::env::consts;
 use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex};
+use std::thread;
 
-fn run_update(base: &Path) -> Result<()> {
-    if let Some(conf) = get_config(base)? {
-        for updatec in conf.update.unwrap_or_default() {
-            if let Some(os) = updatec.os
-                && !os.starts_with(consts::OS)
-            {
-                continue;
-            }
-            match std::process::Command::new(updatec.command)
-                .args(updatec.args)
-                .output()
-            {
-                Ok(out) => eprintln!("{}", sanitize_output(&String::from_utf8(out.stdout)?)),
-                Err(e) => eprintln!("Error: {e:?}"),
-            }
+fn run_update(base: &Path, conf: &Config, report: &mut Report) -> Result<bool> {
+    report.run_hooks(conf, conf.update.as_deref().unwrap_or_default(), base)
+}
+
+#[derive(Default)]
+struct Schedule {
+    started: Vec<bool>,
+    done: Vec<bool>,
+    stopped: bool,
+}
+
+impl Schedule {
+    /// Picks the next directory whose dependencies are done, or `None` when
+    /// nothing is left to start.
+    fn next(&mut self, deps: &[Vec<usize>]) -> Option<Option<usize>> {
+        if self.stopped || self.started.iter().all(|s| *s) {
+            return None;
         }
+        let ready =
+            (0..deps.len()).find(|&i| !self.started[i] && deps[i].iter().all(|&d| self.done[d]));
+        if let Some(i) = ready {
+            self.started[i] = true;
+        }
+        Some(ready)
     }
-    Ok(())
+}
+
+/// Indexes of the configs each config depends on.
+fn dependencies(configs: &[(PathBuf, Config)]) -> Vec<Vec<usize>> {
+    let canonical = configs
+        .iter()
+        .map(|(dir, _)| dir.canonicalize().unwrap_or(dir.clone()))
+        .collect::<Vec<_>>();
+    configs
+        .iter()
+        .map(|(_, conf)| {
+            conf.depends
+                .iter()
+                .flatten()
+                .filter_map(|dep| {
+                    let dep = dep.canonicalize().unwrap_or(dep.clone());
+                    canonical.iter().position(|c| *c == dep)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Runs the update hooks of `configs` with up to `jobs` directories at a time,
+/// starting a directory once the directories it depends on are done.
+fn run_parallel(configs: &[(PathBuf, Config)], jobs: usize) -> Result<Report> {
+    let deps = dependencies(configs);
+    let schedule = Mutex::new(Schedule {
+        started: vec![false; configs.len()],
+        done: vec![false; configs.len()],
+        stopped: false,
+    });
+    let wakeup = Condvar::new();
+    let reports = Mutex::new((0..configs.len()).map(|_| None).collect::<Vec<_>>());
+    thread::scope(|scope| {
+        for _ in 0..jobs.max(1) {
+            scope.spawn(|| {
+                loop {
+                    let i = {
+                        let mut schedule = schedule.lock().unwrap();
+                        loop {
+                            match schedule.next(&deps) {
+                                None => return,
+                                Some(Some(i)) => break i,
+                                Some(None) => schedule = wakeup.wait(schedule).unwrap(),
+                            }
+                        }
+                    };
+                    let (base, conf) = &configs[i];
+                    let mut report = Report::default();
+                    if jobs > 1 {
+                        let name = base.file_name().unwrap_or(base.as_os_str());
+                        report = report.with_prefix(&name.to_string_lossy());
+                    }
+                    let result = run_update(base, conf, &mut report);
+                    let mut schedule = schedule.lock().unwrap();
+                    schedule.done[i] = true;
+                    if !matches!(result, Ok(true)) {
+                        schedule.stopped = true;
+                    }
+                    reports.lock().unwrap()[i] = Some(result.map(|_| report));
+                    wakeup.notify_all();
+                }
+            });
+        }
+    });
+    let mut report = Report::default();
+    for result in reports.into_inner().unwrap().into_iter().flatten() {
+        report.merge(result?);
+    }
+    Ok(report)
 }
 
 #[test]
 fn test_run_update() -> Result<()> {
     let test_base = PathBuf::from("test/repo/bash");
-    run_update(&test_base)?;
-    let file_path = PathBuf::from("testtouch");
+    let configs = ordered_configs(std::slice::from_ref(&test_base))?;
+    let report = run_parallel(&configs, 1)?;
+    report.finish()?;
+    let file_path = test_base.join("testupdate");
     assert!(file_path.exists());
     std::fs::remove_file(&file_path)?;
     assert!(!file_path.exists());
     Ok(())
 }
 
-pub fn run_updates(dirs: &[PathBuf]) -> Result<()> {
-    for dir in dirs {
-        run_update(dir)?
+pub fn run_updates(dirs: &[PathBuf], jobs: usize) -> Result<()> {
+    let configs = ordered_configs(dirs)?;
+    let report = run_parallel(&configs, jobs)?;
+    if configs.len() > 1 {
+        report.print_table();
     }
-    Ok(())
+    report.finish()
 }
@@ -1,14 +1,480 @@
 use crate::config::GlobalConfig;
+use crate::platform::find_executable;
+use crate::repo::host;
+use crate::structs::{display_path, sanitize_display};
+use anyhow::{Context, Result, bail};
+use colored::Colorize;
+use std::collections::{HashSet, VecDeque};
+use std::fs;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::{Component, Path, PathBuf};
+use std::process;
+use std::time::Duration;
 
-pub fn wget(url: &str) -> anyhow::Result<()> {
-    let base_path = GlobalConfig::new().src;
+const INDEX_NAME: &str = "index.html";
+const TIMEOUT: Duration = Duration::from_secs(30);
 
-    let output = std::process::Command::new("wget")
-        .current_dir(&base_path)
-        .args(["-r", url])
-        .output()
-        .expect("installed wget command");
+/// Limits of a recursive fetch, as wget's `--level` and `--no-parent`.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    /// Maximum depth of followed links, 0 for no limit.
+    pub level: u32,
+    /// Only follow links below the directory of the starting URL.
+    pub no_parent: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Url {
+    scheme: String,
+    authority: String,
+    path: String,
+}
+
+impl Url {
+    fn parse(url: &str) -> Result<Url> {
+        let Some((scheme, rest)) = url.split_once("://") else {
+            bail!("not an URL: {}", sanitize_display(url));
+        };
+        let rest = rest.split(['#', '?']).next().unwrap_or_default();
+        let (authority, path) = match rest.find('/') {
+            Some(i) => rest.split_at(i),
+            None => (rest, "/"),
+        };
+        // The host names the directory the site is saved in.
+        let site = host(authority);
+        let mut components = Path::new(site).components();
+        let valid = matches!(components.next(), Some(Component::Normal(_)))
+            && components.next().is_none()
+            && !site.contains('\\');
+        if scheme != "file" && !valid {
+            bail!("invalid host in {}", sanitize_display(url));
+        }
+        Ok(Url {
+            scheme: scheme.to_lowercase(),
+            authority: authority.to_owned(),
+            path: normalize(path),
+        })
+    }
+
+    /// Resolves a link found in the document at this URL, keeping only links
+    /// to the same scheme and authority.
+    fn join(&self, href: &str) -> Option<Url> {
+        let href = href.split(['#', '?']).next().unwrap_or_default();
+        let url = if href.contains("://") {
+            Url::parse(href).ok()?
+        } else if let Some(rest) = href.strip_prefix("//") {
+            Url::parse(&format!("{}://{rest}", self.scheme)).ok()?
+        } else if href.is_empty() || href.split('/').next().is_some_and(|s| s.contains(':')) {
+            return None;
+        } else if href.starts_with('/') {
+            Url {
+                path: normalize(href),
+                ..self.clone()
+            }
+        } else {
+            let dir = &self.path[..self.path.rfind('/').map_or(0, |i| i + 1)];
+            Url {
+                path: normalize(&format!("{dir}{href}")),
+                ..self.clone()
+            }
+        };
+        (url.scheme == self.scheme && url.authority == self.authority).then_some(url)
+    }
+
+    fn site(&self) -> &str {
+        match self.scheme.as_str() {
+            "file" => "localhost",
+            _ => host(&self.authority),
+        }
+    }
+
+    /// Where the document is saved: `<root>/<host>/<path>`, with directories
+    /// saved as their `index.html`.
+    fn local_path(&self, root: &Path) -> PathBuf {
+        let mut path = root.join(self.site());
+        path.extend(self.path.split('/').filter(|s| !s.is_empty()));
+        if self.path.ends_with('/') {
+            path.push(INDEX_NAME);
+        }
+        path
+    }
+
+    fn is_below(&self, parent: &Url) -> bool {
+        let dir = &parent.path[..parent.path.rfind('/').map_or(0, |i| i + 1)];
+        self.path.starts_with(dir)
+    }
+}
+
+impl std::fmt::Display for Url {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}://{}{}", self.scheme, self.authority, self.path)
+    }
+}
+
+/// Resolves `.` and `..` segments of an absolute URL path.
+fn normalize(path: &str) -> String {
+    let mut segments: Vec<&str> = vec![];
+    let parts = path.split('/').collect::<Vec<_>>();
+    for (i, part) in parts.iter().enumerate() {
+        let last = i + 1 == parts.len();
+        match *part {
+            "" | "." if !last => {}
+            ".." => {
+                segments.pop();
+                if last {
+                    segments.push("");
+                }
+            }
+            "." => segments.push(""),
+            part => segments.push(part),
+        }
+    }
+    format!("/{}", segments.join("/"))
+}
+
+/// A fetched document and whether it may contain links.
+struct Document {
+    body: Vec<u8>,
+    html: bool,
+    /// `Location` of a redirect, which has no body worth saving.
+    location: Option<String>,
+}
 
-    eprintln!("{}: {}", url, String::from_utf8(output.stderr)?);
+fn fetch_http(url: &Url) -> Result<Document> {
+    let address = if url.authority.contains(':') {
+        url.authority.clone()
+    } else {
+        format!("{}:80", url.authority)
+    };
+    let mut stream = TcpStream::connect(&address)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    let request = format!(
+        "GET {} HTTP/1.0\r\nHost: {}\r\nUser-Agent: wagon\r\nConnection: close\r\n\r\n",
+        url.path, url.authority
+    );
+    stream.write_all(request.as_bytes())?;
+    let mut response = vec![];
+    stream.read_to_end(&mut response)?;
+    let Some(end) = response.windows(4).position(|w| w == b"\r\n\r\n") else {
+        bail!("malformed HTTP response");
+    };
+    let head = String::from_utf8_lossy(&response[..end]).into_owned();
+    let mut lines = head.lines();
+    let status = lines.next().unwrap_or_default();
+    let code = status.split_whitespace().nth(1).unwrap_or_default();
+    let headers = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim()))
+        .collect::<Vec<_>>();
+    let header = |name: &str| headers.iter().find(|(n, _)| n == name).map(|(_, v)| *v);
+    if code.starts_with('3') {
+        let Some(location) = header("location") else {
+            bail!("HTTP status {} without a Location", sanitize_display(code));
+        };
+        return Ok(Document {
+            body: vec![],
+            html: false,
+            location: Some(location.to_owned()),
+        });
+    }
+    if code != "200" {
+        bail!("HTTP status {}", sanitize_display(code));
+    }
+    Ok(Document {
+        body: response[end + 4..].to_vec(),
+        html: header("content-type").is_some_and(|t| t.contains("text/html")),
+        location: None,
+    })
+}
+
+/// Reads a local file, rendering directories as a page linking their entries.
+fn fetch_file(url: &Url) -> Result<Document> {
+    let path = Path::new(&url.path);
+    if !path.is_dir() {
+        let html = matches!(
+            path.extension().and_then(|e| e.to_str()),
+            Some("html" | "htm")
+        );
+        return Ok(Document {
+            body: fs::read(path)?,
+            html,
+            location: None,
+        });
+    }
+    if !url.path.ends_with('/') {
+        bail!("directory URLs must end with `/`");
+    }
+    let mut names = fs::read_dir(path)?
+        .map(|entry| {
+            let entry = entry?;
+            let mut name = entry.file_name().to_string_lossy().into_owned();
+            if entry.file_type()?.is_dir() {
+                name.push('/');
+            }
+            Ok(name)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    names.sort();
+    let links = names
+        .iter()
+        .map(|name| format!("<a href=\"{name}\">{name}</a>\n"))
+        .collect::<String>();
+    Ok(Document {
+        body: links.into_bytes(),
+        html: true,
+        location: None,
+    })
+}
+
+fn fetch(url: &Url) -> Result<Document> {
+    match url.scheme.as_str() {
+        "http" => fetch_http(url),
+        "file" => fetch_file(url),
+        scheme => bail!("unsupported scheme `{}`", sanitize_display(scheme)),
+    }
+}
+
+/// Values of the `href` and `src` attributes in an HTML document.
+fn links(html: &str) -> Vec<&str> {
+    let mut links = vec![];
+    for attr in ["href=", "src="] {
+        let mut rest = html;
+        while let Some(i) = rest.find(attr) {
+            rest = &rest[i + attr.len()..];
+            let Some(quote) = rest.chars().next().filter(|c| *c == '"' || *c == '\'') else {
+                continue;
+            };
+            rest = &rest[1..];
+            if let Some(end) = rest.find(quote) {
+                links.push(&rest[..end]);
+                rest = &rest[end..];
+            }
+        }
+    }
+    links
+}
+
+fn save(path: &Path, body: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("cannot create {}", parent.display()))?;
+    }
+    fs::write(path, body).with_context(|| format!("cannot write {}", path.display()))
+}
+
+/// Mirrors `start` and the documents it links to below `root`.
+fn mirror(start: &Url, root: &Path, limits: Limits) -> Result<()> {
+    let mut queue = VecDeque::from([(start.clone(), 0)]);
+    let mut seen = HashSet::from([start.clone()]);
+    let (mut saved, mut failed) = (0, 0);
+    while let Some((url, depth)) = queue.pop_front() {
+        let document = match fetch(&url) {
+            Ok(document) => document,
+            Err(err) => {
+                let err = sanitize_display(&format!("{err:#}"));
+                eprintln!("{}: {url} ({err})", "FAILED".red());
+                failed += 1;
+                continue;
+            }
+        };
+        if let Some(location) = &document.location {
+            match url.join(location) {
+                Some(next) if !limits.no_parent || next.is_below(start) => {
+                    eprintln!("{}: {url} -> {next}", "REDIRECT".cyan());
+                    if seen.insert(next.clone()) {
+                        queue.push_back((next, depth));
+                    }
+                }
+                _ => eprintln!(
+                    "{}: {url} -> {} (not followed, outside the mirror)",
+                    "REDIRECT".yellow(),
+                    sanitize_display(location)
+                ),
+            }
+            continue;
+        }
+        let path = url.local_path(root);
+        if let Err(err) = save(&path, &document.body) {
+            let err = sanitize_display(&format!("{err:#}"));
+            eprintln!("{}: {url} ({err})", "FAILED".red());
+            failed += 1;
+            continue;
+        }
+        eprintln!("{}: {}", "SAVED".green(), display_path(&path));
+        saved += 1;
+        if !document.html || limits.level != 0 && depth >= limits.level {
+            continue;
+        }
+        let body = String::from_utf8_lossy(&document.body);
+        for link in links(&body) {
+            let Some(next) = url.join(link) else {
+                continue;
+            };
+            if limits.no_parent && !next.is_below(start) {
+                continue;
+            }
+            if seen.insert(next.clone()) {
+                queue.push_back((next, depth + 1));
+            }
+        }
+    }
+    if failed > 0 {
+        bail!(
+            "{failed} of {} documents failed to download",
+            saved + failed
+        );
+    }
+    Ok(())
+}
+
+/// Fills the `{url}`, `{dest}`, `{root}` and `{level}` placeholders of each
+/// word of the fetcher template.
+fn fetcher_args(template: &str, url: &Url, root: &Path, level: u32) -> Vec<String> {
+    let dest = url.local_path(root);
+    let site = root.join(url.site());
+    template
+        .split_whitespace()
+        .map(|word| {
+            word.replace("{url}", &url.to_string())
+                .replace("{dest}", &dest.to_string_lossy())
+                .replace("{root}", &site.to_string_lossy())
+                .replace("{level}", &level.to_string())
+        })
+        .collect()
+}
+
+fn run_fetcher(args: &[String], root: &Path) -> Result<()> {
+    let Some((program, args)) = args.split_first() else {
+        bail!("empty `fetcher` in the global config");
+    };
+    fs::create_dir_all(root)?;
+    let status = process::Command::new(program)
+        .args(args)
+        .current_dir(root)
+        .status()
+        .with_context(|| format!("cannot run {}", sanitize_display(program)))?;
+    if !status.success() {
+        bail!("{} failed with {status}", sanitize_display(program));
+    }
     Ok(())
 }
+
+/// Downloads `url` recursively under `~/<src>/<host>/<path>`, with the
+/// `fetcher` of the global config, the built-in fetcher for `http` and `file`
+/// URLs, or wget for the others.
+pub fn wget(url: &str, limits: Limits) -> Result<()> {
+    let conf = GlobalConfig::new()?;
+    let url = Url::parse(url)?;
+    let root = dirs::home_dir()
+        .context("cant get home dir")?
+        .join(&conf.src);
+    if let Some(template) = &conf.fetcher {
+        return run_fetcher(&fetcher_args(template, &url, &root, limits.level), &root);
+    }
+    if matches!(url.scheme.as_str(), "http" | "file") {
+        return mirror(&url, &root, limits);
+    }
+    if find_executable("wget").is_none() {
+        bail!(
+            "{} URLs need wget or a `fetcher` in the global config",
+            sanitize_display(&url.scheme)
+        );
+    }
+    let mut template = String::from("wget -r -l {level} -nH -P {root}");
+    if limits.no_parent {
+        template.push_str(" -np");
+    }
+    template.push_str(" {url}");
+    run_fetcher(&fetcher_args(&template, &url, &root, limits.level), &root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_dot_segments() {
+        assert_eq!(normalize("/a/./b/../c"), "/a/c");
+        assert_eq!(normalize("/../../etc/passwd"), "/etc/passwd");
+        assert_eq!(normalize("/docs/.."), "/");
+        assert_eq!(normalize("/docs/"), "/docs/");
+    }
+
+    #[test]
+    fn rejects_hosts_that_are_not_a_directory_name() {
+        for url in [
+            "http://../x",
+            "http://./x",
+            "http:///x",
+            "http://me@:80/x",
+            "http://a\\..\\b/x",
+        ] {
+            assert!(Url::parse(url).is_err(), "{url}");
+        }
+        assert_eq!(
+            Url::parse("http://me@example.com:80/x")
+                .map(|url| url.site().to_owned())
+                .ok(),
+            Some("example.com".to_owned())
+        );
+        assert!(Url::parse("file:///tmp/x").is_ok());
+    }
+
+    #[test]
+    fn joins_links_on_the_same_site() -> Result<()> {
+        let page = Url::parse("http://example.com:8080/docs/guide/index.html?x=1")?;
+        let join = |href| page.join(href).map(|url| url.to_string());
+        assert_eq!(
+            join("intro.html#top").as_deref(),
+            Some("http://example.com:8080/docs/guide/intro.html")
+        );
+        assert_eq!(
+            join("../../style.css").as_deref(),
+            Some("http://example.com:8080/style.css")
+        );
+        assert_eq!(
+            join("/img/logo.png").as_deref(),
+            Some("http://example.com:8080/img/logo.png")
+        );
+        assert_eq!(join("http://other.com/"), None);
+        assert_eq!(join("mailto:me@example.com"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn saves_under_the_host() -> Result<()> {
+        let root = Path::new("/home/user/src");
+        assert_eq!(
+            Url::parse("http://example.com:8080/docs/")?.local_path(root),
+            root.join("example.com/docs/index.html")
+        );
+        assert_eq!(
+            Url::parse("file:///srv/site/a.html")?.local_path(root),
+            root.join("localhost/srv/site/a.html")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn fills_fetcher_placeholders() -> Result<()> {
+        let url = Url::parse("https://example.com/docs/")?;
+        let args = fetcher_args(
+            "httrack {url} -O {root} -r{level}",
+            &url,
+            Path::new("/src"),
+            3,
+        );
+        assert_eq!(
+            args,
+            [
+                "httrack",
+                "https://example.com/docs/",
+                "-O",
+                "/src/example.com",
+                "-r3"
+            ]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,137 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+mod common;
+
+use common::temp_dir;
+
+fn wagon(root: &Path, args: &[&str]) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_wagon"))
+        .current_dir(root)
+        .env("HOME", root)
+        .env("XDG_CONFIG_HOME", root.join(".config"))
+        .env("XDG_STATE_HOME", root.join("state"))
+        .args(["--base"])
+        .arg(root.join("repo"))
+        .args(args)
+        .output()
+        .expect("run wagon");
+    assert!(output.status.success(), "output: {output:?}");
+    output
+}
+
+fn text(output: &Output) -> String {
+    String::from_utf8_lossy(&[output.stdout.as_slice(), output.stderr.as_slice()].concat())
+        .into_owned()
+}
+
+fn write_repo(root: &Path) {
+    let base = root.join("repo");
+    let dest = root.join("home");
+    fs::create_dir_all(&base).expect("create repo");
+    fs::create_dir_all(&dest).expect("create dest");
+    fs::write(base.join(".wagon.toml"), format!("dest = {dest:?}\n")).expect("write config");
+    fs::write(base.join(".bashrc"), "new\n").expect("write source");
+    fs::write(dest.join(".bashrc"), "old\n").expect("write target");
+}
+
+fn user_dir(backups: &Path) -> PathBuf {
+    fs::read_dir(backups)
+        .expect("read backups")
+        .flatten()
+        .map(|entry| entry.path())
+        .find(|path| {
+            path.file_name()
+                .is_some_and(|n| n.to_string_lossy().starts_with("uid"))
+        })
+        .expect("uid dir")
+}
+
+#[test]
+fn link_backs_up_into_state_dir_and_backups_lists_and_prunes() {
+    let root = temp_dir("backups");
+    write_repo(&root);
+
+    wagon(&root, &["link"]);
+    let backups = root.join("state/wagon/backups");
+    let user = user_dir(&backups);
+    let old = user.join("2020/01/02/03:04:05");
+    fs::create_dir_all(&old).expect("create old session");
+    fs::write(old.join(".zshrc"), "zsh\n").expect("write old backup");
+
+    let list = text(&wagon(&root, &["backups"]));
+    let dry_run = text(&wagon(
+        &root,
+        &["backups", "prune", "--older-than", "30d", "--dry-run"],
+    ));
+    let old_kept = old.exists();
+    let prune = text(&wagon(&root, &["backups", "prune", "--older-than", "30d"]));
+    let old_removed = !user.join("2020").exists();
+    let remaining = text(&wagon(&root, &["backups", "ls"]));
+    let cwd_backups = root.join(".backups").exists();
+    let _ = fs::remove_dir_all(&root);
+
+    assert!(!cwd_backups);
+    assert!(list.contains("2020/01/02/03:04:05"), "output: {list}");
+    assert!(list.contains("2 sessions, 2 files, 8B"), "output: {list}");
+    assert!(
+        dry_run.contains("WOULD PRUNE: 2020/01/02/03:04:05"),
+        "output: {dry_run}"
+    );
+    assert!(old_kept);
+    assert!(
+        prune.contains("PRUNED: 2020/01/02/03:04:05 (1 files, 4B)"),
+        "output: {prune}"
+    );
+    assert!(prune.contains("1 of 2 sessions pruned"), "output: {prune}");
+    assert!(old_removed);
+    assert!(
+        remaining.contains("1 sessions, 1 files"),
+        "output: {remaining}"
+    );
+}
+
+#[test]
+fn backup_dir_is_configurable_and_prune_requires_a_rule() {
+    let root = temp_dir("backup-dir");
+    write_repo(&root);
+    fs::create_dir_all(root.join(".config/wagon")).expect("create config dir");
+    fs::write(
+        root.join(".config/wagon/config.toml"),
+        "backup_dir = \"../../saved\"\n",
+    )
+    .expect("write config");
+
+    wagon(&root, &["link"]);
+    let saved = root.join("saved").exists();
+    let prune = Command::new(env!("CARGO_BIN_EXE_wagon"))
+        .env("HOME", &root)
+        .args(["backups", "prune"])
+        .output()
+        .expect("run wagon");
+    let _ = fs::remove_dir_all(&root);
+
+    assert!(saved);
+    assert!(!prune.status.success());
+}
+
+#[test]
+fn backup_root_is_resolved_on_first_backup() {
+    let root = temp_dir("backup-lazy");
+    write_repo(&root);
+    fs::remove_file(root.join("home/.bashrc")).expect("remove target");
+    fs::create_dir_all(root.join(".config/wagon")).expect("create config dir");
+    let global = root.join(".config/wagon/config.toml");
+    fs::write(&global, "backup_dir = [\n").expect("write broken config");
+
+    wagon(&root, &["link"]);
+    let linked = fs::read_link(root.join("home/.bashrc")).is_ok();
+    fs::remove_file(&global).expect("remove broken config");
+    fs::create_dir_all(root.join(".backups/uid0")).expect("create legacy backups");
+    let list = text(&wagon(&root, &["backups"]));
+    let _ = fs::remove_dir_all(&root);
+
+    assert!(linked);
+    assert!(list.contains("earlier version"), "output: {list}");
+}
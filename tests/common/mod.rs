@@ -0,0 +1,11 @@
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A fresh path under the temporary directory, unique per test run.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time")
+        .as_nanos();
+    std::env::temp_dir().join(format!("wagon-{name}-{}-{now}", std::process::id()))
+}
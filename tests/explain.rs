@@ -0,0 +1,140 @@
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::Path;
+use std::process::Command;
+
+mod common;
+
+use common::temp_dir;
+
+fn explain(root: &Path, path: &Path) -> (bool, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_wagon"))
+        .env("HOME", root)
+        .env("XDG_CONFIG_HOME", root.join(".config"))
+        .args(["--base"])
+        .arg(root.join("repo"))
+        .arg("explain")
+        .arg(path)
+        .output()
+        .expect("run wagon");
+    (
+        output.status.success(),
+        String::from_utf8_lossy(&output.stderr).into_owned(),
+    )
+}
+
+#[test]
+fn explain_reports_config_ignore_rule_dirs_and_target() {
+    let root = temp_dir("explain");
+    let base = root.join("repo");
+    let dest = root.join("home");
+    fs::create_dir_all(base.join(".nvim")).expect("create repo");
+    fs::create_dir_all(&dest).expect("create dest");
+    fs::write(base.join(".wagon.toml"), "os = \"plan9\"\n").expect("write config");
+    fs::write(
+        base.join(".wagon.toml.any"),
+        format!("dest = {dest:?}\ndirs = [\".nvim\"]\nignore = [\"*.bak\"]\n"),
+    )
+    .expect("write config");
+    fs::write(base.join(".vimrc"), "set number\n").expect("write source");
+    fs::write(base.join("old.bak"), "").expect("write source");
+    fs::write(base.join(".nvim/init.lua"), "").expect("write source");
+    symlink(base.join(".vimrc"), dest.join(".vimrc")).expect("link vimrc");
+
+    let linked = explain(&root, &dest.join(".vimrc"));
+    let ignored = explain(&root, &base.join("old.bak"));
+    let in_dir = explain(&root, &base.join(".nvim/init.lua"));
+    let missing = explain(&root, &dest.join(".zshrc"));
+    let outside = explain(&root, Path::new("/"));
+    let _ = fs::remove_dir_all(&root);
+
+    let (ok, text) = linked;
+    assert!(ok, "stderr: {text}");
+    assert!(
+        text.contains("SKIPPED: ") && text.contains("`os = \"plan9\"` does not match"),
+        "stderr: {text}"
+    );
+    assert!(text.contains(".wagon.toml.any"), "stderr: {text}");
+    assert!(
+        text.contains("SOURCE: ") && text.contains("repo/.vimrc"),
+        "stderr: {text}"
+    );
+    assert!(
+        text.contains("TARGET: ") && text.contains("STATUS: LINKING"),
+        "stderr: {text}"
+    );
+    let (ok, text) = ignored;
+    assert!(
+        ok && text.contains("IGNORED: by `ignore` glob `*.bak`"),
+        "stderr: {text}"
+    );
+    let (ok, text) = in_dir;
+    assert!(ok && text.contains("DIRS: "), "stderr: {text}");
+    assert!(
+        text.contains("home/.nvim\n") && text.contains("STATUS: NOLINK"),
+        "stderr: {text}"
+    );
+    let (ok, text) = missing;
+    assert!(
+        !ok && text.contains("repo/.zshrc does not exist"),
+        "stderr: {text}"
+    );
+    let (ok, text) = outside;
+    assert!(!ok && text.contains("is neither in"), "stderr: {text}");
+}
+
+#[test]
+fn explain_reports_ignore_files_of_git_above_the_directory() {
+    let root = temp_dir("explain-git");
+    let base = root.join("repo");
+    fs::create_dir_all(root.join(".git/info")).expect("create git dir");
+    fs::create_dir_all(root.join(".config/git")).expect("create git config dir");
+    fs::create_dir_all(&base).expect("create repo");
+    fs::write(root.join(".gitignore"), "*.log\n").expect("write gitignore");
+    fs::write(root.join(".git/info/exclude"), "secret\n").expect("write exclude");
+    fs::write(root.join(".config/git/ignore"), "*.tmp\n").expect("write global excludes");
+    for name in ["a.log", "secret", "x.tmp"] {
+        fs::write(base.join(name), "").expect("write source");
+    }
+
+    let results = ["a.log", "secret", "x.tmp"].map(|name| explain(&root, &base.join(name)));
+    let _ = fs::remove_dir_all(&root);
+
+    for ((ok, text), rule) in results.into_iter().zip(["`*.log`", "`secret`", "`*.tmp`"]) {
+        assert!(
+            ok && text.contains(&format!("IGNORED: by {rule} in")),
+            "stderr: {text}"
+        );
+    }
+}
+
+#[test]
+fn explain_finds_sources_with_mode_prefixes() {
+    let root = temp_dir("explain-dotted");
+    let base = root.join("repo");
+    let dest = root.join("home");
+    fs::create_dir_all(base.join("dot_ssh")).expect("create repo");
+    fs::create_dir_all(&dest).expect("create dest");
+    fs::write(
+        base.join(".wagon.toml"),
+        format!("dest = {dest:?}\ndotted_names = true\nignore = [\"dot_ssh/private_*\"]\n"),
+    )
+    .expect("write config");
+    fs::write(base.join("private_dot_netrc"), "").expect("write source");
+    fs::write(base.join("dot_ssh/private_config"), "").expect("write source");
+
+    let managed = explain(&root, &dest.join(".netrc"));
+    let ignored = explain(&root, &dest.join(".ssh/config"));
+    let _ = fs::remove_dir_all(&root);
+
+    let (ok, text) = managed;
+    assert!(
+        ok && text.contains("repo/private_dot_netrc\n") && text.contains("STATUS: NOLINK"),
+        "stderr: {text}"
+    );
+    let (ok, text) = ignored;
+    assert!(
+        ok && text.contains("repo/dot_ssh/private_config\n") && text.contains("IGNORED: "),
+        "stderr: {text}"
+    );
+}
@@ -0,0 +1,220 @@
+use std::fs;
+use std::process::Command;
+
+mod common;
+
+use common::temp_dir;
+
+#[test]
+fn init_exits_non_zero_and_reports_failed_hooks() {
+    let base = temp_dir("failing-hook");
+    fs::create_dir_all(&base).expect("create repo");
+    fs::write(
+        base.join(".wagon.toml"),
+        r#"
+[[init]]
+command = "echo to-stderr >&2; exit 2"
+shell = true
+continue_on_error = true
+
+[[init]]
+command = "false"
+
+[[init]]
+command = "touch"
+args = ["never"]
+"#,
+    )
+    .expect("write config");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_wagon"))
+        .env("XDG_STATE_HOME", base.join("state"))
+        .args(["--base"])
+        .arg(&base)
+        .arg("init")
+        .output()
+        .expect("run wagon");
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    let never = base.join("never").exists();
+    let _ = fs::remove_dir_all(&base);
+
+    assert!(!output.status.success(), "output: {output:?}");
+    assert!(stderr.contains("to-stderr"), "stderr: {stderr}");
+    assert!(
+        stderr.contains("IGNORED: echo to-stderr"),
+        "stderr: {stderr}"
+    );
+    assert!(stderr.contains("FAILED: false"), "stderr: {stderr}");
+    assert!(!never);
+}
+
+#[test]
+fn init_skips_guarded_and_recorded_hooks() {
+    let base = temp_dir("guarded-hook");
+    fs::create_dir_all(&base).expect("create repo");
+    fs::write(base.join("watched"), "one").expect("write watched file");
+    fs::write(
+        base.join(".wagon.toml"),
+        r#"
+[[init]]
+command = "echo run >> log"
+shell = true
+
+[[init]]
+command = "echo guarded >> log"
+shell = true
+unless = ["test", "-f", "log"]
+
+[[init]]
+command = "echo watch >> log"
+shell = true
+onchange = ["watch*"]
+"#,
+    )
+    .expect("write config");
+    let init = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_wagon"))
+            .env("XDG_STATE_HOME", base.join("state"))
+            .args(["--base"])
+            .arg(&base)
+            .arg("init")
+            .args(args)
+            .output()
+            .expect("run wagon");
+        assert!(output.status.success(), "output: {output:?}");
+        fs::read_to_string(base.join("log")).expect("read log")
+    };
+
+    assert_eq!(init(&[]), "run\nwatch\n");
+    assert_eq!(init(&[]), "run\nwatch\n");
+    fs::write(base.join("watched"), "two").expect("update watched file");
+    assert_eq!(init(&[]), "run\nwatch\nwatch\n");
+    assert_eq!(init(&["--force"]), "run\nwatch\nwatch\nrun\nwatch\n");
+
+    let _ = fs::remove_dir_all(base);
+}
+
+#[test]
+fn post_link_hook_receives_changed_targets() {
+    let root = temp_dir("lifecycle-hook");
+    let base = root.join("repo");
+    let dest = root.join("home");
+    fs::create_dir_all(&base).expect("create repo");
+    fs::create_dir_all(&dest).expect("create dest");
+    fs::write(base.join(".tmux.conf"), "set -g mouse on\n").expect("write source");
+    fs::write(
+        base.join(".wagon.toml"),
+        format!(
+            r#"dest = {dest:?}
+
+[[post_link]]
+command = "cat >> ../linked; echo \"$WAGON_CHANGED\" >> ../changed"
+shell = true
+"#
+        ),
+    )
+    .expect("write config");
+    let link = || {
+        let output = Command::new(env!("CARGO_BIN_EXE_wagon"))
+            .args(["--base"])
+            .arg(&base)
+            .arg("link")
+            .output()
+            .expect("run wagon");
+        assert!(output.status.success(), "output: {output:?}");
+    };
+
+    link();
+    link();
+    let linked = fs::read_to_string(root.join("linked")).expect("read hook stdin");
+    let changed = fs::read_to_string(root.join("changed")).expect("read hook env");
+    let target = format!(
+        "{}\n",
+        dest.canonicalize()
+            .expect("dest")
+            .join(".tmux.conf")
+            .display()
+    );
+    let _ = fs::remove_dir_all(&root);
+
+    assert_eq!(linked, target);
+    assert_eq!(changed, target);
+}
+
+#[test]
+fn init_runs_directory_dependencies_first_and_rejects_cycles() {
+    let base = temp_dir("hook-order");
+    for (dir, config) in [("nvim", "depends = [\"../brew\"]\n"), ("brew", "")] {
+        fs::create_dir_all(base.join(dir)).expect("create dir");
+        fs::write(
+            base.join(dir).join(".wagon.toml"),
+            format!("{config}[[init]]\ncommand = \"echo {dir} >> ../log\"\nshell = true\n"),
+        )
+        .expect("write config");
+    }
+    let init = || {
+        Command::new(env!("CARGO_BIN_EXE_wagon"))
+            .env("XDG_STATE_HOME", base.join("state"))
+            .args(["--base"])
+            .arg(&base)
+            .args(["init", "nvim"])
+            .output()
+            .expect("run wagon")
+    };
+
+    let output = init();
+    assert!(output.status.success(), "output: {output:?}");
+    let log = fs::read_to_string(base.join("log")).expect("read log");
+
+    fs::write(base.join("brew/.wagon.toml"), "depends = [\"../nvim\"]\n").expect("write config");
+    let cycle = init();
+    let _ = fs::remove_dir_all(&base);
+
+    assert_eq!(log, "brew\nnvim\n");
+    assert!(!cycle.status.success());
+    assert!(
+        String::from_utf8_lossy(&cycle.stderr).contains("dependency cycle between directories"),
+        "output: {cycle:?}"
+    );
+}
+
+#[test]
+fn parallel_update_prefixes_output_and_times_out_hooks() {
+    let base = temp_dir("parallel-update");
+    for (dir, hook) in [
+        ("slow", "command = \"sleep 5\"\nshell = true\ntimeout = 1\n"),
+        ("fast", "command = \"echo updated\"\nshell = true\n"),
+        (
+            "probe",
+            "command = \"echo probed\"\nunless = [\"sleep\", \"5\"]\ntimeout = 1\n",
+        ),
+    ] {
+        fs::create_dir_all(base.join(dir)).expect("create dir");
+        fs::write(
+            base.join(dir).join(".wagon.toml"),
+            format!("[[update]]\n{hook}"),
+        )
+        .expect("write config");
+    }
+
+    let output = Command::new(env!("CARGO_BIN_EXE_wagon"))
+        .args(["--base"])
+        .arg(&base)
+        .args(["update", "--jobs", "3", "slow", "fast", "probe"])
+        .output()
+        .expect("run wagon");
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    let _ = fs::remove_dir_all(&base);
+
+    assert!(!output.status.success(), "output: {output:?}");
+    assert!(stderr.contains("[fast] updated"), "stderr: {stderr}");
+    assert!(stderr.contains("timed out after 1s"), "stderr: {stderr}");
+    assert!(
+        stderr.contains("`unless` probe: timed out after 1s") && !stderr.contains("[probe] probed"),
+        "stderr: {stderr}"
+    );
+    let table = stderr
+        .lines()
+        .filter(|l| l.starts_with("TIMEOUT") || l.starts_with("OK"));
+    assert_eq!(table.count(), 3, "stderr: {stderr}");
+}
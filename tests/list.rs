@@ -0,0 +1,386 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+mod common;
+
+use common::temp_dir;
+
+/// Creates a repo whose `.vimrc` differs from the one already in the dest.
+fn write_repo(root: &Path) -> PathBuf {
+    let base = root.join("repo");
+    let dest = root.join("home");
+    fs::create_dir_all(&base).expect("create repo");
+    fs::create_dir_all(&dest).expect("create dest");
+    fs::write(base.join(".wagon.toml"), format!("dest = {dest:?}\n")).expect("write config");
+    fs::write(base.join(".vimrc"), "set number\nset hidden\nsyntax on\n").expect("write source");
+    fs::write(dest.join(".vimrc"), "set number\nsyntax off\n").expect("write target");
+    base
+}
+
+fn list(root: &Path, args: &[&str]) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_wagon"))
+        .env("HOME", root)
+        .env("XDG_CONFIG_HOME", root.join(".config"))
+        .args(["--base"])
+        .arg(root.join("repo"))
+        .arg("ls")
+        .args(args)
+        .output()
+        .expect("run wagon");
+    assert!(output.status.success(), "output: {output:?}");
+    output
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn list_shows_stat_or_no_diff() {
+    let root = temp_dir("list-stat");
+    write_repo(&root);
+
+    let stat = stderr(&list(&root, &["--stat"]));
+    let no_diff = stderr(&list(&root, &["--no-diff"]));
+    let _ = fs::remove_dir_all(&root);
+
+    assert!(stat.contains("+1 -2"), "stderr: {stat}");
+    assert!(
+        stat.contains("1 files changed, 1 insertions(+), 2 deletions(-)"),
+        "stderr: {stat}"
+    );
+    assert!(no_diff.contains("EXISTS"), "stderr: {no_diff}");
+    assert!(!no_diff.contains("syntax"), "stderr: {no_diff}");
+}
+
+#[test]
+fn list_runs_diff_tool_from_option_or_global_config() {
+    let root = temp_dir("list-diff-tool");
+    let base = write_repo(&root);
+    fs::create_dir_all(root.join(".config/wagon")).expect("create config dir");
+    fs::write(
+        root.join(".config/wagon/config.toml"),
+        "diff_tool = \"diff -U0\"\n",
+    )
+    .expect("write config");
+
+    let configured = list(&root, &[]);
+    let option = list(&root, &["--diff-tool", "echo {target} {source}"]);
+    let context = list(&root, &["--diff-tool", "echo -U{context}", "-U", "7"]);
+    let _ = fs::remove_dir_all(&root);
+
+    let configured = String::from_utf8_lossy(&configured.stdout).into_owned();
+    assert!(configured.contains("-set hidden"), "stdout: {configured}");
+    assert!(!configured.contains(" set number"), "stdout: {configured}");
+    assert_eq!(
+        String::from_utf8_lossy(&option.stdout).trim_end(),
+        format!(
+            "{} {}",
+            root.join("home/.vimrc").display(),
+            base.join(".vimrc").display()
+        )
+    );
+    assert!(
+        String::from_utf8_lossy(&context.stdout).starts_with("-U7 "),
+        "output: {context:?}"
+    );
+}
+
+#[test]
+fn list_compares_binary_and_large_files_without_diffing() {
+    let root = temp_dir("list-binary");
+    let base = write_repo(&root);
+    fs::write(base.join("image.bin"), b"\x89PNG\x00\x01").expect("write source");
+    fs::write(root.join("home/image.bin"), b"\x89PNG\x00").expect("write target");
+    fs::create_dir_all(root.join(".config/wagon")).expect("create config dir");
+    fs::write(
+        root.join(".config/wagon/config.toml"),
+        "max_diff_size = 16\n",
+    )
+    .expect("write config");
+
+    let output = stderr(&list(&root, &[]));
+    let _ = fs::remove_dir_all(&root);
+
+    assert!(
+        output.contains("binary files do not match. src size:6, dst size:5"),
+        "stderr: {output}"
+    );
+    assert!(
+        output.contains("files too large to diff. src size:32, dst size:22"),
+        "stderr: {output}"
+    );
+}
+
+#[test]
+fn list_shows_changed_settings_and_hex_dumps() {
+    let root = temp_dir("list-structured");
+    let base = write_repo(&root);
+    fs::write(
+        base.join("settings.json"),
+        "{\n  // font\n  \"editor.fontSize\": 14,\n  \"editor.tabSize\": 2,\n}\n",
+    )
+    .expect("write source");
+    fs::write(
+        root.join("home/settings.json"),
+        "{\"editor.tabSize\": 2, \"editor.fontSize\": 13}",
+    )
+    .expect("write target");
+    fs::write(base.join("icon.bin"), b"\x00\x01\x02\x03").expect("write source");
+    fs::write(root.join("home/icon.bin"), b"\x00\x01\x02\x04").expect("write target");
+
+    let output = stderr(&list(&root, &[]));
+    let _ = fs::remove_dir_all(&root);
+
+    assert!(
+        output.contains("~ \"editor.fontSize\": 14 -> 13"),
+        "stderr: {output}"
+    );
+    assert!(!output.contains("tabSize"), "stderr: {output}");
+    assert!(
+        output.contains("-00000000  00 01 02 03"),
+        "stderr: {output}"
+    );
+    assert!(
+        output.contains("+00000000  00 01 02 04"),
+        "stderr: {output}"
+    );
+}
+
+#[test]
+fn list_compares_directory_links_recursively() {
+    let root = temp_dir("list-dirs");
+    let base = write_repo(&root);
+    fs::write(
+        base.join(".wagon.toml"),
+        format!("dest = {:?}\ndirs = [\".zsh\"]\n", root.join("home")),
+    )
+    .expect("write config");
+    fs::create_dir_all(base.join(".zsh/functions")).expect("create repo dir");
+    fs::create_dir_all(root.join("home/.zsh/cache")).expect("create target dir");
+    fs::write(base.join(".zsh/functions/up"), "cd ..\n").expect("write source");
+    fs::write(base.join(".zsh/env.zsh"), "export A=1\n").expect("write source");
+    fs::write(root.join("home/.zsh/env.zsh"), "export A=2\n").expect("write target");
+
+    let output = stderr(&list(&root, &[]));
+    fs::write(base.join(".zsh/stale"), "").expect("write source");
+    std::os::unix::fs::symlink(root.join("missing"), root.join("home/.zsh/stale"))
+        .expect("break target");
+    let failed = list(&root, &[]);
+    let _ = fs::remove_dir_all(&root);
+
+    assert!(
+        output.contains("ONLY IN REPO: ") && output.contains("repo/.zsh/functions\n"),
+        "stderr: {output}"
+    );
+    assert!(
+        output.contains("ONLY IN TARGET: ") && output.contains("home/.zsh/cache\n"),
+        "stderr: {output}"
+    );
+    assert!(
+        output.contains("DIFFERS: ") && output.contains("home/.zsh/env.zsh -> "),
+        "stderr: {output}"
+    );
+    assert!(output.contains("+export A=2"), "stderr: {output}");
+    let failed = stderr(&failed);
+    assert!(
+        failed.contains("ERROR: cannot compare: ") && failed.contains("home/.zsh -> "),
+        "stderr: {failed}"
+    );
+}
+
+#[test]
+fn list_filters_by_status_and_path_and_summarizes() {
+    let root = temp_dir("list-filter");
+    let base = write_repo(&root);
+    let home = root.join("home");
+    for name in [".bashrc", ".zshrc", ".tmux.conf"] {
+        fs::write(base.join(name), name).expect("write source");
+    }
+    std::os::unix::fs::symlink(base.join(".tmux.conf"), home.join(".tmux.conf"))
+        .expect("link tmux");
+    std::os::unix::fs::symlink(root.join("missing"), home.join(".zshrc")).expect("break zshrc");
+
+    let only = stderr(&list(&root, &["--only", "nolink,broken", "--no-diff"]));
+    let excluded = stderr(&list(&root, &["--exclude-status", "exists,nolink,broken"]));
+    let paths = stderr(&list(
+        &root,
+        &["--path", "*rc", "--exclude-status", "broken"],
+    ));
+    let summary = stderr(&list(&root, &["--summary"]));
+    let _ = fs::remove_dir_all(&root);
+
+    assert!(
+        only.contains("NOLINK: ") && only.contains(".bashrc"),
+        "stderr: {only}"
+    );
+    assert!(only.contains("broken symlink"), "stderr: {only}");
+    assert!(
+        only.lines()
+            .any(|l| l.starts_with("BROKEN: ") && l.contains(".zshrc")),
+        "stderr: {only}"
+    );
+    assert!(
+        !only.contains("EXISTS") && !only.contains("LINKING"),
+        "stderr: {only}"
+    );
+    let excluded = excluded.lines().skip(1).collect::<Vec<_>>();
+    assert_eq!(excluded.len(), 1, "stderr: {excluded:?}");
+    assert!(excluded[0].starts_with("LINKING: "));
+    assert!(
+        paths.contains(".vimrc") && paths.contains(".bashrc"),
+        "stderr: {paths}"
+    );
+    assert!(
+        !paths.contains(".tmux.conf") && !paths.contains(".zshrc"),
+        "stderr: {paths}"
+    );
+    assert!(
+        summary.contains("LINKING: 1, NOLINK: 1, EXISTS: 1, BROKEN: 1"),
+        "stderr: {summary}"
+    );
+}
+
+#[test]
+fn list_renders_tree_and_table() {
+    let root = temp_dir("list-format");
+    let base = write_repo(&root);
+    fs::create_dir_all(base.join(".config/git")).expect("create source dir");
+    fs::write(base.join(".config/git/config"), "[user]\n").expect("write source");
+
+    let tree = stderr(&list(&root, &["--format", "tree"]));
+    let table = stderr(&list(&root, &["--format", "table"]));
+    let narrow = Command::new(env!("CARGO_BIN_EXE_wagon"))
+        .env("HOME", &root)
+        .env("XDG_CONFIG_HOME", root.join(".config"))
+        .env("COLUMNS", "60")
+        .args(["--base"])
+        .arg(&base)
+        .args(["ls", "--format", "table"])
+        .output()
+        .expect("run wagon");
+    let _ = fs::remove_dir_all(&root);
+
+    assert!(tree.contains("├── .config"), "stderr: {tree}");
+    assert!(tree.contains("│   └── git"), "stderr: {tree}");
+    assert!(tree.contains("config  NOLINK -> "), "stderr: {tree}");
+    assert!(tree.contains("└── .vimrc  EXISTS -> "), "stderr: {tree}");
+    assert!(!tree.contains("syntax"), "stderr: {tree}");
+    let rows = table.lines().skip(1).collect::<Vec<_>>();
+    assert!(rows[0].starts_with("STATUS  TARGET"), "stderr: {table}");
+    let exists = rows.iter().find(|r| r.starts_with("EXISTS")).expect("row");
+    assert!(exists.contains(" 22B "), "stderr: {table}");
+    assert!(rows.iter().any(|r| r.ends_with("  -")), "stderr: {table}");
+    let narrow = stderr(&narrow);
+    assert!(
+        narrow.lines().all(|line| line.chars().count() <= 60),
+        "stderr: {narrow}"
+    );
+    assert!(narrow.contains('…'), "stderr: {narrow}");
+}
+
+#[test]
+fn list_applies_ignore_rules_and_includes() {
+    let root = temp_dir("list-ignore");
+    let base = write_repo(&root);
+    let dest = root.join("home");
+    fs::write(
+        base.join(".wagon.toml"),
+        format!(
+            "dest = {dest:?}\n\
+             ignore = [\"README.md\", {{ path = \".work*\", when = {{ env = [\"WAGON_PROFILE=home\"] }} }}]\n\
+             include = [\".config/git/.gitignore\"]\n"
+        ),
+    )
+    .expect("write config");
+    fs::create_dir_all(base.join(".config/git")).expect("create source dir");
+    fs::create_dir_all(root.join(".config/wagon")).expect("create config dir");
+    fs::write(root.join(".config/wagon/ignore"), "*.swp\n").expect("write global ignore");
+    fs::write(base.join(".wagonignore"), "!.gitmodules\n").expect("write wagonignore");
+    for name in [
+        ".config/git/.gitignore",
+        ".gitignore",
+        ".gitmodules",
+        "README.md",
+        ".vimrc.swp",
+        ".workrc",
+    ] {
+        fs::write(base.join(name), name).expect("write source");
+    }
+
+    let run = |profile: &str| {
+        let output = Command::new(env!("CARGO_BIN_EXE_wagon"))
+            .env("HOME", &root)
+            .env("XDG_CONFIG_HOME", root.join(".config"))
+            .env("WAGON_PROFILE", profile)
+            .args(["--base"])
+            .arg(&base)
+            .args(["ls", "--no-diff"])
+            .output()
+            .expect("run wagon");
+        assert!(output.status.success(), "output: {output:?}");
+        stderr(&output)
+    };
+    let home = run("home");
+    let work = run("work");
+    let _ = fs::remove_dir_all(&root);
+
+    assert!(home.contains(".config/git/.gitignore"), "stderr: {home}");
+    assert!(home.contains(".gitmodules"), "stderr: {home}");
+    assert!(
+        !home.contains("home/.gitignore") && !home.contains("README.md"),
+        "stderr: {home}"
+    );
+    assert!(!home.contains(".swp"), "stderr: {home}");
+    assert!(!home.contains(".workrc"), "stderr: {home}");
+    assert!(work.contains(".workrc"), "stderr: {work}");
+}
+
+#[test]
+fn list_shows_git_ignored_files_unless_respected() {
+    let root = temp_dir("list-gitignore");
+    let base = write_repo(&root);
+    let dest = root.join("home");
+    let git = Command::new("git")
+        .args(["init", "-q"])
+        .arg(&base)
+        .status()
+        .expect("run git");
+    assert!(git.success());
+    fs::write(base.join(".gitignore"), ".bashrc.local\n").expect("write gitignore");
+    fs::write(base.join(".ignore"), ".zshrc.local\n").expect("write ignore");
+    fs::write(base.join(".bashrc.local"), "local\n").expect("write source");
+    fs::write(base.join(".zshrc.local"), "local\n").expect("write source");
+    let config = |extra: &str| {
+        fs::write(
+            base.join(".wagon.toml"),
+            format!("dest = {dest:?}\n{extra}"),
+        )
+        .expect("write config");
+    };
+
+    let respected = stderr(&list(&root, &["--no-diff"]));
+    config("respect_gitignore = false\n");
+    let unrespected = stderr(&list(&root, &["--no-diff"]));
+    config("ignore_sources = [\"wagonignore\"]\n");
+    let only_wagonignore = stderr(&list(&root, &["--no-diff"]));
+    let _ = fs::remove_dir_all(&root);
+
+    assert!(
+        !respected.contains(".bashrc.local") && !respected.contains(".zshrc.local"),
+        "stderr: {respected}"
+    );
+    assert!(
+        unrespected.contains(".bashrc.local"),
+        "stderr: {unrespected}"
+    );
+    assert!(
+        !unrespected.contains(".zshrc.local"),
+        "stderr: {unrespected}"
+    );
+    assert!(
+        only_wagonignore.contains(".bashrc.local") && only_wagonignore.contains(".zshrc.local"),
+        "stderr: {only_wagonignore}"
+    );
+}
@@ -0,0 +1,228 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+mod common;
+
+use common::temp_dir;
+
+fn git(dir: &Path, args: &[&str]) {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .env("GIT_AUTHOR_NAME", "wagon")
+        .env("GIT_AUTHOR_EMAIL", "wagon@example.com")
+        .env("GIT_COMMITTER_NAME", "wagon")
+        .env("GIT_COMMITTER_EMAIL", "wagon@example.com")
+        .output()
+        .expect("run git");
+    assert!(output.status.success(), "git {args:?}: {output:?}");
+}
+
+fn commit(dir: &Path, file: &str) {
+    fs::write(dir.join(file), file).expect("write file");
+    git(dir, &["add", "."]);
+    git(dir, &["commit", "--quiet", "-m", file]);
+}
+
+#[test]
+fn sync_clones_fast_forwards_and_skips_dirty_repos() {
+    let root = temp_dir("repo-sync");
+    let home = root.join("home");
+    let origins = root.join("origins");
+    for name in ["clean", "dirty"] {
+        let origin = origins.join("owner").join(name);
+        fs::create_dir_all(&origin).expect("create origin");
+        git(&origin, &["init", "--quiet"]);
+        commit(&origin, "one");
+    }
+    fs::create_dir_all(home.join(".config/wagon")).expect("create config dir");
+    fs::write(
+        home.join(".config/wagon/config.toml"),
+        format!(
+            "repos = [\"file://{}\"]\nrepos_file = \"repos.txt\"\n",
+            origins.join("owner/clean").display()
+        ),
+    )
+    .expect("write config");
+    fs::write(
+        home.join(".config/wagon/repos.txt"),
+        format!(
+            "# local\nfile://{}\n",
+            origins.join("owner/dirty").display()
+        ),
+    )
+    .expect("write repo list");
+    let sync = || {
+        let output = Command::new(env!("CARGO_BIN_EXE_wagon"))
+            .env("HOME", &home)
+            .env("XDG_CONFIG_HOME", home.join(".config"))
+            .args(["repo", "sync", "--jobs", "2"])
+            .output()
+            .expect("run wagon");
+        assert!(output.status.success(), "output: {output:?}");
+        String::from_utf8_lossy(&output.stderr).into_owned()
+    };
+
+    let first = sync();
+    let clean = home.join("src/localhost/owner/clean");
+    let dirty = home.join("src/localhost/owner/dirty");
+    commit(&origins.join("owner/clean"), "two");
+    commit(&origins.join("owner/dirty"), "two");
+    fs::write(dirty.join("one"), "local change").expect("dirty the clone");
+    let second = sync();
+    let pulled = clean.join("two").exists();
+    let skipped = !dirty.join("two").exists();
+    let third = sync();
+    let _ = fs::remove_dir_all(&root);
+
+    assert_eq!(first.matches("CLONED").count(), 2, "stderr: {first}");
+    assert!(second.contains("UPDATED"), "stderr: {second}");
+    assert!(second.contains("DIRTY"), "stderr: {second}");
+    assert!(pulled && skipped);
+    assert!(third.contains("UP TO DATE"), "stderr: {third}");
+}
+
+#[test]
+fn list_shows_state_and_path_finds_best_match() {
+    let home = temp_dir("repo-list");
+    for name in ["wagon-tools", "wagon"] {
+        let repo = home.join("src/github.com/owner").join(name);
+        fs::create_dir_all(&repo).expect("create repo");
+        git(&repo, &["init", "--quiet", "--initial-branch", "main"]);
+        commit(&repo, "one");
+    }
+    fs::write(home.join("src/github.com/owner/wagon/one"), "changed").expect("dirty repo");
+    let wagon = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_wagon"))
+            .env("HOME", &home)
+            .env("XDG_CONFIG_HOME", home.join(".config"))
+            .arg("repo")
+            .args(args)
+            .output()
+            .expect("run wagon");
+        assert!(output.status.success(), "output: {output:?}");
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+
+    let list = wagon(&["list"]);
+    let path = wagon(&["path", "wagon"]);
+    let fuzzy = wagon(&["path", "wgntls"]);
+    let _ = fs::remove_dir_all(&home);
+
+    let rows = list.lines().skip(1).collect::<Vec<_>>();
+    assert_eq!(rows.len(), 2, "list: {list}");
+    assert!(
+        rows[0].starts_with("github.com/owner/wagon "),
+        "list: {list}"
+    );
+    assert!(
+        rows[0].contains("main") && rows[0].contains("dirty"),
+        "list: {list}"
+    );
+    assert!(
+        rows[1].contains("clean") && rows[1].contains("no upstream"),
+        "list: {list}"
+    );
+    assert_eq!(
+        path.trim_end(),
+        home.join("src/github.com/owner/wagon")
+            .display()
+            .to_string()
+    );
+    assert!(
+        fuzzy.trim_end().ends_with("owner/wagon-tools"),
+        "path: {fuzzy}"
+    );
+}
+
+#[test]
+fn clone_runs_hooks_in_worktree_layout_and_fails_on_errors() {
+    let root = temp_dir("repo-clone");
+    let home = root.join("home");
+    let origin = root.join("origins/owner/tool");
+    fs::create_dir_all(&origin).expect("create origin");
+    git(&origin, &["init", "--quiet", "--initial-branch", "main"]);
+    fs::write(
+        origin.join(".wagon.toml"),
+        "[[init]]\ncommand = \"touch\"\nargs = [\"initialized\"]\n",
+    )
+    .expect("write repo config");
+    commit(&origin, "one");
+    fs::create_dir_all(home.join(".config/wagon")).expect("create config dir");
+    fs::write(
+        home.join(".config/wagon/config.toml"),
+        "[clone]\ndepth = 1\n\n[[post_clone]]\ncommand = \"echo \\\"$WAGON_CHANGED\\\" > ../cloned\"\nshell = true\n",
+    )
+    .expect("write config");
+    let wagon = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_wagon"))
+            .env("HOME", &home)
+            .env("XDG_CONFIG_HOME", home.join(".config"))
+            .env("XDG_STATE_HOME", root.join("state"))
+            .arg("repo")
+            .args(args)
+            .output()
+            .expect("run wagon")
+    };
+
+    let url = format!("file://{}", origin.display());
+    let cloned = wagon(&["clone", "--worktree", "--init", &url]);
+    let repo = home.join("src/localhost/owner/tool");
+    let worktree = repo.join("main");
+    let layout = repo.join(".bare").is_dir() && worktree.join("one").is_file();
+    let initialized = worktree.join("initialized").exists();
+    let hook = fs::read_to_string(repo.join("cloned")).unwrap_or_default();
+    let missing = format!("file://{}", root.join("origins/owner/missing").display());
+    let failed = wagon(&[&missing]);
+    let leftover = home.join("src/localhost/owner/missing").exists();
+    let _ = fs::remove_dir_all(&root);
+
+    assert!(cloned.status.success(), "output: {cloned:?}");
+    assert_eq!(
+        String::from_utf8_lossy(&cloned.stdout).trim_end(),
+        repo.display().to_string()
+    );
+    assert!(layout);
+    assert!(initialized);
+    assert_eq!(hook.trim_end(), worktree.display().to_string());
+    assert!(!failed.status.success(), "output: {failed:?}");
+    assert!(String::from_utf8_lossy(&failed.stderr).contains("FAILED"));
+    assert!(!leftover);
+}
+
+#[test]
+fn clone_reports_failing_post_clone_hooks_apart_from_clone_errors() {
+    let root = temp_dir("repo-post-clone");
+    let home = root.join("home");
+    let origin = root.join("origins/owner/tool");
+    fs::create_dir_all(&origin).expect("create origin");
+    git(&origin, &["init", "--quiet"]);
+    commit(&origin, "one");
+    fs::create_dir_all(home.join(".config/wagon")).expect("create config dir");
+    fs::write(
+        home.join(".config/wagon/config.toml"),
+        "[[post_clone]]\ncommand = \"false\"\n",
+    )
+    .expect("write config");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_wagon"))
+        .env("HOME", &home)
+        .env("XDG_CONFIG_HOME", home.join(".config"))
+        .env("XDG_STATE_HOME", root.join("state"))
+        .args(["repo", "clone"])
+        .arg(format!("file://{}", origin.display()))
+        .output()
+        .expect("run wagon");
+    let kept = home.join("src/localhost/owner/tool/one").is_file();
+    let _ = fs::remove_dir_all(&root);
+
+    assert!(!output.status.success(), "output: {output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("post-clone hook failed in ") && stderr.contains("the clone was kept"),
+        "stderr: {stderr}"
+    );
+    assert!(kept);
+}
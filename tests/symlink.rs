@@ -1,16 +1,11 @@
 use std::fs;
-use std::os::unix::fs::symlink;
-use std::path::{Path, PathBuf};
+use std::os::unix::fs::{PermissionsExt, symlink};
+use std::path::Path;
 use std::process::Command;
-use std::time::{SystemTime, UNIX_EPOCH};
-
-fn temp_dir(name: &str) -> PathBuf {
-    let now = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .expect("system time")
-        .as_nanos();
-    std::env::temp_dir().join(format!("wagon-{name}-{}-{now}", std::process::id()))
-}
+
+mod common;
+
+use common::temp_dir;
 
 fn write_repo(base: &Path, dest: &Path) {
     fs::create_dir_all(base).expect("create repo");
@@ -34,6 +29,7 @@ fn link_reports_and_replaces_broken_target_symlink() {
 
     let output = Command::new(env!("CARGO_BIN_EXE_wagon"))
         .current_dir(&root)
+        .env("XDG_STATE_HOME", root.join("state"))
         .args(["--base"])
         .arg(&base)
         .arg("link")
@@ -65,6 +61,7 @@ fn link_reports_broken_source_symlink_and_continues() {
 
     let output = Command::new(env!("CARGO_BIN_EXE_wagon"))
         .current_dir(&root)
+        .env("XDG_STATE_HOME", root.join("state"))
         .args(["--base"])
         .arg(&base)
         .arg("link")
@@ -99,6 +96,7 @@ fn list_reports_directory_symlink_conflict_without_diffing() {
 
     let output = Command::new(env!("CARGO_BIN_EXE_wagon"))
         .current_dir(&root)
+        .env("XDG_STATE_HOME", root.join("state"))
         .args(["--base"])
         .arg(&base)
         .arg("ls")
@@ -113,3 +111,86 @@ fn list_reports_directory_symlink_conflict_without_diffing() {
 
     let _ = fs::remove_dir_all(root);
 }
+
+#[test]
+fn link_translates_dotted_names() {
+    let root = temp_dir("dotted-names");
+    let base = root.join("repo");
+    let dest = root.join("home");
+    fs::create_dir_all(base.join("dot_ssh")).expect("create repo");
+    fs::create_dir_all(&dest).expect("create dest");
+    fs::write(
+        base.join(".wagon.toml"),
+        format!("dest = {dest:?}\ndotted_names = true\n"),
+    )
+    .expect("write config");
+    fs::write(base.join("dot_bashrc"), "new\n").expect("write source");
+    fs::write(base.join("dot_ssh/private_config"), "Host *\n").expect("write source");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_wagon"))
+        .current_dir(&root)
+        .env("XDG_STATE_HOME", root.join("state"))
+        .args(["--base"])
+        .arg(&base)
+        .arg("link")
+        .output()
+        .expect("run wagon");
+
+    assert!(output.status.success(), "command failed: {output:?}");
+    assert_eq!(
+        fs::read_link(dest.join(".bashrc")).expect("read linked file"),
+        base.join("dot_bashrc")
+            .canonicalize()
+            .expect("canonical source")
+    );
+    let config = dest.join(".ssh/config");
+    assert!(fs::read_link(&config).is_ok());
+    assert_eq!(
+        fs::metadata(&config)
+            .expect("linked config")
+            .permissions()
+            .mode()
+            & 0o777,
+        0o600
+    );
+
+    let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn link_merges_existing_directory_into_repo() {
+    let root = temp_dir("merge-dir");
+    let base = root.join("repo");
+    let dest = root.join("home");
+    fs::create_dir_all(base.join(".zsh")).expect("create repo dir");
+    fs::create_dir_all(dest.join(".zsh/local")).expect("create target dir");
+    fs::write(
+        base.join(".wagon.toml"),
+        format!("dest = {dest:?}\ndirs = [\".zsh\"]\n"),
+    )
+    .expect("write config");
+    fs::write(base.join(".zsh/aliases.zsh"), "alias ll='ls -l'\n").expect("write source");
+    fs::write(dest.join(".zsh/aliases.zsh"), "alias l='ls'\n").expect("write target");
+    fs::write(dest.join(".zsh/local/path.zsh"), "path+=(~/bin)\n").expect("write target");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_wagon"))
+        .current_dir(&root)
+        .env("XDG_STATE_HOME", root.join("state"))
+        .args(["--base"])
+        .arg(&base)
+        .args(["link", "--merge"])
+        .output()
+        .expect("run wagon");
+    let merged = fs::read_to_string(base.join(".zsh/local/path.zsh")).unwrap_or_default();
+    let kept = fs::read_to_string(base.join(".zsh/aliases.zsh")).unwrap_or_default();
+    let linked = fs::read_link(dest.join(".zsh")).ok();
+    let _ = fs::remove_dir_all(&root);
+
+    assert!(output.status.success(), "output: {output:?}");
+    assert_eq!(merged, "path+=(~/bin)\n");
+    assert_eq!(kept, "alias ll='ls -l'\n");
+    assert!(linked.is_some());
+    let text = output_text(&output);
+    assert!(text.contains("MERGED:"), "output: {text}");
+    assert!(text.contains("CONFLICT:"), "output: {text}");
+}
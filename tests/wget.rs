@@ -0,0 +1,175 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::{Command, Output};
+use std::thread;
+
+mod common;
+
+use common::temp_dir;
+
+/// Serves a small site on a local port and returns its address.
+fn serve() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind listener");
+    let address = listener.local_addr().expect("local address").to_string();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.expect("accept");
+            let mut reader = BufReader::new(&stream);
+            let mut request = String::new();
+            reader.read_line(&mut request).expect("read request");
+            let mut header = String::new();
+            while reader.read_line(&mut header).is_ok_and(|n| n > 2) {
+                header.clear();
+            }
+            let path = request.split_whitespace().nth(1).unwrap_or_default();
+            let location = match path {
+                "/moved" => "/docs/deep/page.txt",
+                "/away" => "http://other.invalid/",
+                _ => "",
+            };
+            let (status, kind, body) = match path {
+                "/docs/" => (
+                    "200 OK",
+                    "text/html",
+                    r#"<a href="guide.html">guide</a> <a href="../private.txt">up</a>"#,
+                ),
+                "/docs/guide.html" => (
+                    "200 OK",
+                    "text/html",
+                    r#"<a href='deep/page.txt'>deep</a><a href="http://other.invalid/">x</a>"#,
+                ),
+                "/docs/deep/page.txt" => ("200 OK", "text/plain", "deep"),
+                "/private.txt" => ("200 OK", "text/plain", "private"),
+                "/mixed/" => (
+                    "200 OK",
+                    "text/html",
+                    r#"<a href="notes">notes</a> <a href="notes/a.txt">a</a> <a href="b.txt">b</a>"#,
+                ),
+                "/mixed/notes" | "/mixed/notes/a.txt" | "/mixed/b.txt" => {
+                    ("200 OK", "text/plain", "text")
+                }
+                "/moved" => ("301 Moved Permanently", "text/plain", ""),
+                "/away" => ("302 Found", "text/plain", ""),
+                _ => ("404 Not Found", "text/plain", "missing"),
+            };
+            let location = if location.is_empty() {
+                String::new()
+            } else {
+                format!("Location: {location}\r\n")
+            };
+            let _ = write!(
+                stream,
+                "HTTP/1.0 {status}\r\n{location}Content-Type: {kind}\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len()
+            );
+        }
+    });
+    address
+}
+
+fn wget(home: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_wagon"))
+        .env("HOME", home)
+        .env("XDG_CONFIG_HOME", home.join(".config"))
+        .arg("wget")
+        .args(args)
+        .output()
+        .expect("run wagon")
+}
+
+#[test]
+fn mirrors_http_sites_within_limits() {
+    let home = temp_dir("wget-http");
+    let address = serve();
+    let url = format!("http://{address}/docs/");
+
+    let shallow = wget(&home, &["--level", "1", "--no-parent", &url]);
+    let site = home.join("src/127.0.0.1");
+    let guide = site.join("docs/guide.html").is_file();
+    let deep_after_shallow = site.join("docs/deep/page.txt").exists();
+    let private_after_shallow = site.join("private.txt").exists();
+    let full = wget(&home, &[&url]);
+    let deep = fs::read_to_string(site.join("docs/deep/page.txt")).unwrap_or_default();
+    let private = site.join("private.txt").exists();
+    let missing = wget(&home, &[&format!("http://{address}/nothing")]);
+    let _ = fs::remove_dir_all(&home);
+
+    assert!(shallow.status.success(), "output: {shallow:?}");
+    assert!(guide);
+    assert!(!deep_after_shallow && !private_after_shallow);
+    assert!(full.status.success(), "output: {full:?}");
+    assert_eq!(deep, "deep");
+    assert!(private);
+    assert!(!missing.status.success(), "output: {missing:?}");
+}
+
+#[test]
+fn follows_redirects_and_reports_write_errors() {
+    let home = temp_dir("wget-redirect");
+    let address = serve();
+    let site = home.join("src/127.0.0.1");
+
+    let moved = wget(&home, &[&format!("http://{address}/moved")]);
+    let deep = fs::read_to_string(site.join("docs/deep/page.txt")).unwrap_or_default();
+    let away = wget(&home, &[&format!("http://{address}/away")]);
+    let mixed = wget(&home, &[&format!("http://{address}/mixed/")]);
+    let after_conflict = site.join("mixed/b.txt").is_file();
+    let _ = fs::remove_dir_all(&home);
+
+    assert!(moved.status.success(), "output: {moved:?}");
+    assert!(String::from_utf8_lossy(&moved.stderr).contains("REDIRECT: "));
+    assert_eq!(deep, "deep");
+    assert!(away.status.success(), "output: {away:?}");
+    assert!(String::from_utf8_lossy(&away.stderr).contains("not followed"));
+    assert!(!mixed.status.success(), "output: {mixed:?}");
+    let stderr = String::from_utf8_lossy(&mixed.stderr);
+    assert!(stderr.contains("FAILED: ") && stderr.contains("notes/a.txt"));
+    assert!(stderr.contains("1 of 4 documents failed"));
+    assert!(after_conflict);
+}
+
+#[test]
+fn mirrors_file_urls_and_runs_configured_fetcher() {
+    let root = temp_dir("wget-file");
+    let home = root.join("home");
+    let site = root.join("site");
+    fs::create_dir_all(site.join("sub")).expect("create site");
+    fs::write(site.join("sub/a.txt"), "a").expect("write file");
+
+    let mirrored = wget(&home, &[&format!("file://{}/", site.display())]);
+    let copied = home
+        .join("src/localhost")
+        .join(site.strip_prefix("/").expect("absolute"))
+        .join("sub/a.txt");
+    let copied = fs::read_to_string(copied).unwrap_or_default();
+
+    let script = root.join("fetch.sh");
+    fs::write(&script, "#!/bin/sh\necho \"$@\" > fetched\n").expect("write fetcher");
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).expect("chmod fetcher");
+    fs::create_dir_all(home.join(".config/wagon")).expect("create config dir");
+    fs::write(
+        home.join(".config/wagon/config.toml"),
+        format!(
+            "fetcher = \"{} {{url}} {{root}} {{level}}\"\n",
+            script.display()
+        ),
+    )
+    .expect("write config");
+    let fetched = wget(&home, &["-l", "2", "https://example.com/docs/"]);
+    let args = fs::read_to_string(home.join("src/fetched")).unwrap_or_default();
+    let _ = fs::remove_dir_all(&root);
+
+    assert!(mirrored.status.success(), "output: {mirrored:?}");
+    assert_eq!(copied, "a");
+    assert!(fetched.status.success(), "output: {fetched:?}");
+    assert_eq!(
+        args.trim_end(),
+        format!(
+            "https://example.com/docs/ {} 2",
+            home.join("src/example.com").display()
+        )
+    );
+}